@@ -0,0 +1,118 @@
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, RwLock};
+
+use array2d::Array2D;
+use egui::Vec2;
+use rayon::prelude::*;
+
+use crate::simulation::Simulation;
+use crate::{copy_particle_positions, SimResults, UiEvent, MAX_CLASSES, MAX_PARTICLE_COUNT};
+
+/// Min number of simulations shown by the basin explorer
+/// (including the main one).
+pub const MIN_BASIN_RUNS: usize = 2;
+/// Max number of simulations shown by the basin explorer
+/// (including the main one).
+pub const MAX_BASIN_RUNS: usize = 16;
+
+/// Positions and particle counts of the extra runs of the basin
+/// explorer, shared with the ui like the [`PositionsBuffer`](crate::PositionsBuffer).
+pub type BasinBuffer = Arc<RwLock<Vec<(Array2D<Vec2>, [usize; MAX_CLASSES])>>>;
+
+/// A simulation run alongside the main one, with its own
+/// channels.
+struct BasinRun {
+    simulation: Simulation,
+    ui_send: Sender<UiEvent>,
+    sim_rcv: Receiver<SimResults>,
+}
+
+/// Runs copies of the main simulation with the same settings but
+/// different random spawns, to see if a seed always ends up in
+/// the same state (attractor).
+pub struct BasinExplorer {
+    runs: Vec<BasinRun>,
+    buffer: BasinBuffer,
+}
+
+impl BasinExplorer {
+    /// Creates `run_count` copies of `simulation` and spawns their
+    /// particles.
+    pub fn new(simulation: &Simulation, run_count: usize, buffer: BasinBuffer) -> Self {
+        let runs = (0..run_count)
+            .map(|_| {
+                let (ui_send, ui_rcv) = channel::<UiEvent>();
+                let (sim_send, sim_rcv) = channel::<SimResults>();
+                let positions_buffer = Arc::new(RwLock::new(Array2D::filled_with(
+                    Vec2::ZERO,
+                    MAX_CLASSES,
+                    MAX_PARTICLE_COUNT,
+                )));
+                BasinRun {
+                    simulation: simulation.fork(sim_send, ui_rcv, positions_buffer),
+                    ui_send,
+                    sim_rcv,
+                }
+            })
+            .collect();
+
+        let mut explorer = Self { runs, buffer };
+        explorer.write_buffer();
+        explorer
+    }
+
+    /// Sends the event to all the runs, events that only concern
+    /// the main simulation are ignored.
+    pub fn forward(&self, event: &UiEvent) {
+        if matches!(
+            event,
+            UiEvent::Quit
+                | UiEvent::SetThreadCount(_)
+                | UiEvent::SetSendVelocities(_)
+                | UiEvent::SetCountAdjacencies(_)
+                | UiEvent::StartBasinExplorer(_, _)
+                | UiEvent::StopBasinExplorer
+                | UiEvent::InjectParticle(_, _)
+                | UiEvent::DeleteParticle(_, _)
+                | UiEvent::SaveState(_, _)
+                | UiEvent::LoadState(_)
+        ) {
+            return;
+        }
+        for run in &self.runs {
+            run.ui_send.send(event.to_owned()).unwrap();
+        }
+    }
+
+    /// Applies the forwarded events, and moves the particles of
+    /// every run if `running`.
+    pub fn update(&mut self, running: bool) {
+        self.runs.par_iter_mut().for_each(|run| {
+            run.simulation.handle_events();
+            if running {
+                run.simulation.move_particles();
+            }
+            // Nobody reads the results of the runs.
+            run.sim_rcv.try_iter().for_each(drop);
+        });
+        self.write_buffer();
+    }
+
+    fn write_buffer(&self) {
+        let mut buffer = self.buffer.write().unwrap();
+        buffer.resize_with(self.runs.len(), || {
+            (
+                Array2D::filled_with(Vec2::ZERO, MAX_CLASSES, MAX_PARTICLE_COUNT),
+                [0; MAX_CLASSES],
+            )
+        });
+        for (run, (positions, particle_counts)) in self.runs.iter().zip(buffer.iter_mut()) {
+            *particle_counts = run.simulation.particle_counts();
+            copy_particle_positions(
+                run.simulation.particle_positions(),
+                positions,
+                particle_counts,
+            );
+        }
+    }
+}
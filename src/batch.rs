@@ -0,0 +1,147 @@
+use std::fmt::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::channel;
+use std::sync::{Arc, RwLock};
+
+use array2d::Array2D;
+use egui::Vec2;
+
+use crate::simulation::Simulation;
+use crate::{SimResults, UiEvent, MAX_CLASSES, MAX_PARTICLE_COUNT};
+
+/// What the seeds explored by the [`BatchSeedRunner`] are ranked
+/// by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BatchMetric {
+    /// Final [`Simulation::order_parameter`], the highest first.
+    OrderParameter,
+    /// Final [`Simulation::mean_particle_distance`], the lowest
+    /// (most compact) first.
+    MeanDistance,
+}
+
+impl BatchMetric {
+    pub fn name(&self) -> &'static str {
+        match self {
+            BatchMetric::OrderParameter => "order parameter",
+            BatchMetric::MeanDistance => "mean distance",
+        }
+    }
+
+    fn score(&self, simulation: &Simulation) -> f32 {
+        match self {
+            BatchMetric::OrderParameter => simulation.order_parameter(),
+            BatchMetric::MeanDistance => simulation.mean_particle_distance(),
+        }
+    }
+
+    /// Whether a higher score ranks first.
+    fn higher_is_better(&self) -> bool {
+        match self {
+            BatchMetric::OrderParameter => true,
+            BatchMetric::MeanDistance => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BatchResult {
+    pub seed: String,
+    pub score: f32,
+}
+
+/// Number of worker threads of each simulation run by the
+/// [`BatchSeedRunner`], so that the main simulation keeps the
+/// shared thread pool for itself.
+const BATCH_THREAD_COUNT: usize = 1;
+/// Ticks run between two checks of the stop flag.
+const STOP_CHECK_TICKS: usize = 100;
+
+/// Runs seeds without the ui for a fixed number of ticks and ranks
+/// them by a [`BatchMetric`], instead of randomizing repeatedly by
+/// hand.
+pub struct BatchSeedRunner {
+    /// Seeds and the events that set up a simulation for them
+    /// (seeds are decoded by the ui, which also adds its current
+    /// settings).
+    seeds: Vec<(String, Vec<UiEvent>)>,
+    tick_count: usize,
+    metric: BatchMetric,
+    /// Set by the ui to stop the run early, the seeds that were
+    /// fully run are still ranked.
+    stop: Arc<AtomicBool>,
+}
+
+impl BatchSeedRunner {
+    pub fn new(
+        seeds: Vec<(String, Vec<UiEvent>)>,
+        tick_count: usize,
+        metric: BatchMetric,
+        stop: Arc<AtomicBool>,
+    ) -> Self {
+        Self {
+            seeds,
+            tick_count,
+            metric,
+            stop,
+        }
+    }
+
+    fn stopped(&self) -> bool {
+        self.stop.load(Ordering::Relaxed)
+    }
+
+    /// Runs the seeds one after the other and returns them from the
+    /// best to the worst.
+    pub fn run(self) -> Vec<BatchResult> {
+        let mut results = Vec::new();
+        for (seed, events) in &self.seeds {
+            // The simulation unwraps its sends, so the channels
+            // are kept open until the end of the run.
+            let (ui_send, ui_rcv) = channel();
+            let (sim_send, _sim_rcv) = channel::<SimResults>();
+            let positions_buffer = Arc::new(RwLock::new(Array2D::filled_with(
+                Vec2::ZERO,
+                MAX_CLASSES,
+                MAX_PARTICLE_COUNT,
+            )));
+            let mut simulation = Simulation::new(sim_send, ui_rcv, positions_buffer);
+            ui_send
+                .send(UiEvent::SetThreadCount(BATCH_THREAD_COUNT))
+                .unwrap();
+            for event in events {
+                ui_send.send(event.to_owned()).unwrap();
+            }
+            simulation.handle_events();
+
+            let mut ticks = 0;
+            while ticks < self.tick_count && !self.stopped() {
+                let n = STOP_CHECK_TICKS.min(self.tick_count - ticks);
+                simulation.step_n(n);
+                ticks += n;
+            }
+            if self.stopped() {
+                break;
+            }
+            results.push(BatchResult {
+                seed: seed.to_owned(),
+                score: self.metric.score(&simulation),
+            });
+        }
+
+        results.sort_by(|a, b| a.score.total_cmp(&b.score));
+        if self.metric.higher_is_better() {
+            results.reverse();
+        }
+        results
+    }
+}
+
+/// Writes the ranked results as csv, one seed per row.
+pub fn results_csv(results: &[BatchResult]) -> String {
+    let mut csv = String::from("rank,seed,score\n");
+    for (rank, result) in results.iter().enumerate() {
+        writeln!(csv, "{},{},{}", rank + 1, result.seed, result.score).unwrap();
+    }
+    csv
+}
@@ -0,0 +1,104 @@
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, RwLock};
+use std::time::Instant;
+
+use array2d::Array2D;
+use egui::Vec2;
+use rand::rngs::SmallRng;
+use rand::{Rng, SeedableRng};
+use serde_json::{json, Value};
+
+use crate::simulation::Simulation;
+use crate::{
+    Param, SimResults, UiEvent, UpdateSharedState, MAX_CLASSES, MAX_FORCE, MAX_PARTICLE_COUNT,
+    MAX_RADIUS, MIN_FORCE, MIN_RADIUS,
+};
+
+/// Particle count of each class when benchmarking.
+pub const BENCHMARK_PARTICLE_COUNT: usize = 500;
+
+/// Runs the simulation without the ui and measures how long it
+/// takes.
+pub struct BenchmarkRunner {
+    simulation: Simulation,
+
+    // The simulation unwraps its sends, so the channels are kept
+    // open even though nobody listens.
+    _ui_send: Sender<UiEvent>,
+    _sim_rcv: Receiver<SimResults>,
+}
+
+impl BenchmarkRunner {
+    pub fn new(particle_counts: [usize; MAX_CLASSES]) -> Self {
+        let (ui_send, ui_rcv) = channel::<UiEvent>();
+        let (sim_send, sim_rcv) = channel::<SimResults>();
+        let positions_buffer = Arc::new(RwLock::new(Array2D::filled_with(
+            Vec2::ZERO,
+            MAX_CLASSES,
+            MAX_PARTICLE_COUNT,
+        )));
+
+        // Random forces from a fixed seed so the particles move like
+        // they would in a real run and every run is the same.
+        let mut rand = SmallRng::seed_from_u64(0);
+        let param_matrix = Array2D::filled_by_row_major(
+            || {
+                Param::new(
+                    rand.gen_range(MIN_FORCE..=MAX_FORCE),
+                    rand.gen_range(MIN_RADIUS..=MAX_RADIUS),
+                )
+            },
+            MAX_CLASSES,
+            MAX_CLASSES,
+        );
+
+        let simulation = Simulation::builder()
+            .particle_counts(particle_counts)
+            .param_matrix(param_matrix)
+            .build(sim_send, ui_rcv, positions_buffer);
+
+        Self {
+            simulation,
+            _ui_send: ui_send,
+            _sim_rcv: sim_rcv,
+        }
+    }
+
+    /// Spawns the particles and runs `tick_count` ticks, returns
+    /// the timing report.
+    pub fn run(&mut self, tick_count: usize) -> Value {
+        let start_time = Instant::now();
+        self.simulation.spawn();
+        let spawn_time = start_time.elapsed();
+
+        let start_time = Instant::now();
+        let tick_times: Vec<f64> = (0..tick_count)
+            .map(|_| {
+                let tick_start_time = Instant::now();
+                self.simulation.move_particles();
+                tick_start_time.elapsed().as_secs_f64() * 1000.
+            })
+            .collect();
+        let elapsed = start_time.elapsed();
+
+        // Half width of the 95% confidence interval of the mean,
+        // used to tell if two runs actually differ.
+        let n = tick_times.len().max(1) as f64;
+        let mean = tick_times.iter().sum::<f64>() / n;
+        let variance =
+            tick_times.iter().map(|t| (t - mean).powi(2)).sum::<f64>() / (n - 1.).max(1.);
+        let ci95 = 1.96 * (variance / n).sqrt();
+
+        let particle_count = self.simulation.enabled_particle_count();
+        let interaction_count = (particle_count * particle_count * tick_count) as f64;
+        json!({
+            "tick_count": tick_count,
+            "particle_count": particle_count,
+            "spawn_time_ms": spawn_time.as_secs_f64() * 1000.,
+            "wall_time_ms": elapsed.as_secs_f64() * 1000.,
+            "mean_tick_time_ms": mean,
+            "tick_time_ci95_ms": ci95,
+            "interactions_per_second": interaction_count / elapsed.as_secs_f64(),
+        })
+    }
+}
@@ -0,0 +1,63 @@
+use std::fmt::Write;
+
+use array2d::Array2D;
+use eframe::epaint::Color32;
+use egui::Vec2;
+
+use crate::ui::PARTICLE_DIAMETER;
+
+/// Same as the background of the canvas.
+const SVG_BACKGROUND_COLOR: Color32 = Color32::from_rgb(12, 12, 12);
+
+/// Draws the particles the way they are shown on a canvas of size
+/// `canvas_size`, `colors` holds the color of each enabled class.
+/// `view_pos` and `zoom` are the ones of the view (the canvas
+/// center is at `view_pos` in simulation space, scaled by `zoom`).
+pub fn export_as_svg(
+    particle_positions: &Array2D<Vec2>,
+    particle_counts: &[usize],
+    colors: &[Color32],
+    view_pos: Vec2,
+    zoom: f32,
+    canvas_size: Vec2,
+) -> String {
+    let mut svg = String::new();
+    writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}">"#,
+        w = canvas_size.x,
+        h = canvas_size.y,
+    )
+    .unwrap();
+    writeln!(
+        svg,
+        r#"<rect width="100%" height="100%" fill="{}"/>"#,
+        svg_color(SVG_BACKGROUND_COLOR)
+    )
+    .unwrap();
+
+    let center = canvas_size / 2. + view_pos * zoom;
+    for (c, color) in colors.iter().enumerate() {
+        writeln!(svg, r#"<g fill="{}">"#, svg_color(*color)).unwrap();
+        for p in 0..particle_counts[c] {
+            let pos = center + particle_positions[(c, p)] * zoom;
+            if pos.x < 0. || pos.y < 0. || pos.x > canvas_size.x || pos.y > canvas_size.y {
+                continue;
+            }
+            writeln!(
+                svg,
+                r#"<circle cx="{:.2}" cy="{:.2}" r="{}"/>"#,
+                pos.x, pos.y, PARTICLE_DIAMETER
+            )
+            .unwrap();
+        }
+        svg.push_str("</g>\n");
+    }
+
+    svg.push_str("</svg>\n");
+    svg
+}
+
+fn svg_color(color: Color32) -> String {
+    format!("#{:02x}{:02x}{:02x}", color.r(), color.g(), color.b())
+}
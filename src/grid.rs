@@ -0,0 +1,62 @@
+use std::collections::HashMap;
+
+use egui::Vec2;
+
+/// Particles (class and index) sorted into square cells, so that
+/// the particles close to one are found without going through
+/// all of them.
+pub struct CellGrid {
+    cell_size: f32,
+    cells: HashMap<(i32, i32), Vec<(usize, usize)>>,
+}
+
+impl CellGrid {
+    pub fn new(cell_size: f32) -> Self {
+        Self {
+            cell_size,
+            cells: HashMap::new(),
+        }
+    }
+
+    pub fn cell_of(&self, pos: Vec2) -> (i32, i32) {
+        (
+            (pos.x / self.cell_size).floor() as i32,
+            (pos.y / self.cell_size).floor() as i32,
+        )
+    }
+
+    /// Replaces the particles of the grid. The cells that are still
+    /// occupied keep their allocation, meant to be called every
+    /// tick.
+    pub fn fill(&mut self, particles: impl IntoIterator<Item = ((usize, usize), Vec2)>) {
+        for particles in self.cells.values_mut() {
+            particles.clear();
+        }
+        for (particle, pos) in particles {
+            let cell = self.cell_of(pos);
+            self.cells.entry(cell).or_default().push(particle);
+        }
+        self.cells.retain(|_, particles| !particles.is_empty());
+    }
+
+    pub fn cells(&self) -> impl Iterator<Item = ((i32, i32), &[(usize, usize)])> {
+        self.cells
+            .iter()
+            .map(|(cell, particles)| (*cell, particles.as_slice()))
+    }
+
+    pub fn cells_mut(&mut self) -> impl Iterator<Item = &mut Vec<(usize, usize)>> {
+        self.cells.values_mut()
+    }
+
+    /// Particles in the given cell and in the 8 around it, all the
+    /// particles closer than the cell size to one of the cell are
+    /// among them.
+    pub fn neighbors(&self, (x, y): (i32, i32)) -> impl Iterator<Item = (usize, usize)> + '_ {
+        (-1..=1)
+            .flat_map(move |dx| (-1..=1).map(move |dy| (x + dx, y + dy)))
+            .filter_map(|cell| self.cells.get(&cell))
+            .flatten()
+            .copied()
+    }
+}
@@ -1,87 +1,159 @@
-use std::sync::mpsc::channel;
-use std::thread;
-use std::time::Duration;
+use std::env;
+use std::path::PathBuf;
+use std::process;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, OnceLock, RwLock};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use array2d::Array2D;
 use eframe::epaint::Color32;
 use eframe::NativeOptions;
-use egui::Vec2;
-use simulation::SimulationState;
+use egui::{Context, Vec2};
+use rayon::ThreadPoolBuilder;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use simulation::{BoundaryShape, SimulationState, SpawnShape};
 use ui::Smarticles;
 
+use crate::basin::BasinBuffer;
+use crate::benchmark::{BenchmarkRunner, BENCHMARK_PARTICLE_COUNT};
 use crate::simulation::Simulation;
+use crate::storage::SmartConfig;
 
+mod basin;
+mod batch;
+mod benchmark;
+mod export;
+mod grid;
+mod recording;
 mod simulation;
+mod storage;
 mod ui;
 
-// IDEA Add recordings ? By exporting positions of all the
-// particles each frame ? That would make around 8000 postions
-// every 1/60 second that is to say 60*8000=480,000 positions
-// per second, let's assume a position is 8 bytes (from Vec2),
-// then one second of simulation is 8*480,000=3,840,000 bytes
-// this is around 4MB. 1min of simulation is 60*4=240MB.
-// This seems possible, although not for long recordings.
-// Saving the exact starting position might also work although
-// if the simulation runs for too long there might be differences
-// between computers.
-
 /// Min number of particle classes in the simulation.
 const MIN_CLASSES: usize = 3;
 /// Max number of particle classes in the simulation.
 const MAX_CLASSES: usize = 8;
 
+/// Max number of threads the simulation can be set to use.
+const MAX_THREAD_COUNT: usize = 32;
+
 /// Min particle count.
 const MIN_PARTICLE_COUNT: usize = 0;
 /// Maximal particle count per class.
 const MAX_PARTICLE_COUNT: usize = 1200;
 /// When randomizing particle counts, this is the lowest
 /// possible value, this prevent random particle counts from
-/// being under this value.
+/// being under this value. Built-in default of
+/// [`SmartConfig::random_min_particle_count`].
 const RANDOM_MIN_PARTICLE_COUNT: usize = 200;
 /// When randomizing particle counts, this is the highest
 /// possible value, this prevent random particle counts from
-/// being above this value.
+/// being above this value. Built-in default of
+/// [`SmartConfig::random_max_particle_count`].
 const RANDOM_MAX_PARTICLE_COUNT: usize = 1000;
 
 const DEFAULT_FORCE: f32 = 0.;
 const MAX_FORCE: f32 = 100.;
 const MIN_FORCE: f32 = -MAX_FORCE;
+/// Long-range forces are meant to be weak compared to the main
+/// force.
+const MAX_LONG_RANGE_FORCE: f32 = 0.2 * MAX_FORCE;
+const MIN_LONG_RANGE_FORCE: f32 = -MAX_LONG_RANGE_FORCE;
 const FORCE_FACTOR: f32 = 0.001;
+/// Repulsion between particles closer than the ramp start
+/// radius, higher values pack the particles more tightly.
+const DEFAULT_CLOSE_FORCE: f32 = 20.;
+const MIN_CLOSE_FORCE: f32 = 1.;
+const MAX_CLOSE_FORCE: f32 = 100.;
+
+const DEFAULT_MASS: f32 = 1.;
+const MIN_MASS: f32 = 0.1;
+const MAX_MASS: f32 = 10.;
+
+/// Max probability for a particle to give birth or to die
+/// during a tick.
+const MAX_POPULATION_RATE: f32 = 0.01;
+
+/// Number of particles per unit of area in the spawn area, the
+/// spawn radius grows with the number of particles. At much
+/// lower densities, the particles spawn too far apart to
+/// interact, hence the range.
+const DEFAULT_SPAWN_DENSITY: f32 = 1.;
+const MIN_SPAWN_DENSITY: f32 = 0.1;
+const MAX_SPAWN_DENSITY: f32 = 10.;
+
+const MAX_TEMPERATURE: f32 = 100.;
+const MAX_GRAVITY: f32 = 500.;
+/// Scales the gravity set in the ui to the velocity it adds
+/// every tick.
+const GRAVITY_FACTOR: f32 = 0.1 * FORCE_FACTOR;
+const DEFAULT_ATTRACTOR_STRENGTH: f32 = 20.;
+const MAX_ATTRACTOR_STRENGTH: f32 = 100.;
+const MAX_VORTEX_STRENGTH: f32 = 100.;
+
+/// Size of the window in windowed mode when no size was saved.
+const DEFAULT_WINDOW_SIZE: Vec2 = Vec2::new(1600., 900.);
 
 const DEFAULT_RADIUS: f32 = 80.;
 const MIN_RADIUS: f32 = 30.;
 const MAX_RADIUS: f32 = 100.;
 
 fn main() {
-    let options = NativeOptions {
-        // initial_window_size: Some(Vec2::new(1600., 900.)),
-        fullscreen: true,
-        ..Default::default()
+    let windowed = has_flag("--windowed");
+    let options = if windowed {
+        let geometry = storage::load_window_geometry();
+        NativeOptions {
+            initial_window_pos: geometry.and_then(|geometry| geometry.position),
+            initial_window_size: Some(
+                geometry.map_or(DEFAULT_WINDOW_SIZE, |geometry| geometry.size),
+            ),
+            ..Default::default()
+        }
+    } else {
+        NativeOptions {
+            fullscreen: true,
+            ..Default::default()
+        }
     };
 
     env_logger::init();
 
-    let (ui_send, ui_rcv) = channel::<UiEvent>();
-    let (sim_send, sim_rcv) = channel::<SimResults>();
+    if let Some(thread_count) = parse_arg::<usize>("--threads") {
+        ThreadPoolBuilder::new()
+            .num_threads(thread_count)
+            .build_global()
+            .unwrap();
+    }
+
+    CONFIG
+        .set(storage::load_config(parse_arg::<PathBuf>("--config")))
+        .unwrap();
+
+    if let Some(class_limit) = parse_arg::<usize>("--classes") {
+        if !(MIN_CLASSES..=MAX_CLASSES).contains(&class_limit) {
+            eprintln!(
+                "--classes must be between {} and {}",
+                MIN_CLASSES, MAX_CLASSES
+            );
+            process::exit(1);
+        }
+        CLASS_LIMIT.set(class_limit).unwrap();
+    }
+
+    if let Some(tick_count) = parse_arg::<usize>("--benchmark") {
+        let report = BenchmarkRunner::new([BENCHMARK_PARTICLE_COUNT; MAX_CLASSES]).run(tick_count);
+        println!("{}", report);
+        return;
+    }
 
     eframe::run_native(
         "Smarticles",
         options,
         Box::new(|cc| {
-            let frame = cc.egui_ctx.clone();
-
-            let simulation_handle = thread::spawn(move || {
-                let mut simulation = Simulation::new(sim_send, ui_rcv);
-                thread::sleep(Duration::from_millis(500));
-
-                loop {
-                    if !simulation.update() {
-                        break;
-                    };
-                    frame.request_repaint();
-                }
-            });
-
             Box::new(Smarticles::new(
                 [
                     ("α", Color32::from_rgb(247, 0, 243)),
@@ -93,9 +165,8 @@ fn main() {
                     ("η", Color32::from_rgb(255, 140, 0)),
                     ("θ", Color32::from_rgb(255, 0, 0)),
                 ],
-                ui_send,
-                sim_rcv,
-                Some(simulation_handle),
+                spawn_simulation(cc.egui_ctx.clone()),
+                windowed,
             ))
         }),
     );
@@ -119,7 +190,89 @@ fn main() {
     // ("θ", Color32::from_rgb(247, 142, 240)),
 }
 
-#[derive(Debug)]
+/// Number of classes that can be enabled, set with `--classes`.
+static CLASS_LIMIT: OnceLock<usize> = OnceLock::new();
+
+fn class_limit() -> usize {
+    CLASS_LIMIT.get().copied().unwrap_or(MAX_CLASSES)
+}
+
+/// Startup defaults, read from the config file at startup.
+static CONFIG: OnceLock<SmartConfig> = OnceLock::new();
+
+fn config() -> &'static SmartConfig {
+    CONFIG.get_or_init(SmartConfig::default)
+}
+
+/// Returns the value following `name` in the command line
+/// arguments (e.g. `--benchmark 100`), if any.
+fn parse_arg<T: FromStr>(name: &str) -> Option<T> {
+    let mut args = env::args().skip_while(|arg| arg != name).skip(1);
+    args.next()?.parse().ok()
+}
+
+/// Whether `name` is in the command line arguments (e.g.
+/// `--windowed`).
+fn has_flag(name: &str) -> bool {
+    env::args().any(|arg| arg == name)
+}
+
+/// Everything needed to communicate with a running simulation
+/// thread.
+struct SimulationThread {
+    ui_send: Sender<UiEvent>,
+    sim_rcv: Receiver<SimResults>,
+    positions_buffer: PositionsBuffer,
+    /// Time (in ms since the unix epoch) at which the simulation
+    /// last started an update.
+    heartbeat: Arc<AtomicU64>,
+    handle: JoinHandle<()>,
+}
+
+/// Starts a simulation in a new thread, `ctx` is used to request
+/// a repaint after each update.
+fn spawn_simulation(ctx: Context) -> SimulationThread {
+    let (ui_send, ui_rcv) = channel::<UiEvent>();
+    let (sim_send, sim_rcv) = channel::<SimResults>();
+    let positions_buffer: PositionsBuffer = Arc::new(RwLock::new(Array2D::filled_with(
+        Vec2::ZERO,
+        MAX_CLASSES,
+        MAX_PARTICLE_COUNT,
+    )));
+    let heartbeat = Arc::new(AtomicU64::new(epoch_millis()));
+
+    let sim_positions_buffer = positions_buffer.clone();
+    let sim_heartbeat = heartbeat.clone();
+    let handle = thread::spawn(move || {
+        let mut simulation = Simulation::new(sim_send, ui_rcv, sim_positions_buffer);
+        thread::sleep(Duration::from_millis(500));
+
+        loop {
+            sim_heartbeat.store(epoch_millis(), Ordering::Relaxed);
+            if !simulation.update() {
+                break;
+            };
+            ctx.request_repaint();
+        }
+    });
+
+    SimulationThread {
+        ui_send,
+        sim_rcv,
+        positions_buffer,
+        heartbeat,
+        handle,
+    }
+}
+
+fn epoch_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64
+}
+
+#[derive(Debug, Clone)]
 enum UiEvent {
     Play,
     Pause,
@@ -130,22 +283,149 @@ enum UiEvent {
     ParamsUpdate(Array2D<Param>),
     ClassCountUpdate(usize),
     ParticleCountsUpdate([usize; MAX_CLASSES]),
+    ClassMassesUpdate([f32; MAX_CLASSES]),
+    /// Birth and death rates of each class.
+    PopulationRatesUpdate([f32; MAX_CLASSES], [f32; MAX_CLASSES]),
+    SetThreadCount(usize),
+    SetTemperature(f32),
+    SetGravity(Vec2),
+    SetAttractor(AttractorMode),
+    SetVortexStrength(f32),
+    /// Confinement shape and coefficient of restitution.
+    SetBoundary(BoundaryShape, f32),
+    SetMaxParticlesPerCell(Option<usize>),
+    /// Only used by the next spawn.
+    SetSpawnDensity(f32),
+    /// Takes effect on the next tick.
+    SetCloseForce(f32),
+    /// Only used by the next spawn.
+    SetSpawnShape(SpawnShape),
+    /// Whether the velocities are added to the [`SimResults`].
+    SetSendVelocities(bool),
+    /// Whether [`Simulation::adjacency_counts`] is computed.
+    SetCountAdjacencies(bool),
+    /// Adds a particle of the given class at the given position.
+    InjectParticle(usize, Vec2),
+    /// Removes the given particle (class and index).
+    DeleteParticle(usize, usize),
+    /// Pins the particles of the class in place, they still act on
+    /// the other particles.
+    FreezeClass(usize),
+    UnfreezeClass(usize),
+    /// Moves the particles this many ticks ahead at once, even when
+    /// paused.
+    Step(usize),
+    /// Runs the given number of extra simulations, their
+    /// positions are written to the buffer.
+    StartBasinExplorer(usize, BasinBuffer),
+    StopBasinExplorer,
+    /// Writes [`Simulation::to_json`] to the given file, the
+    /// outcome is sent back through the sender.
+    SaveState(PathBuf, Sender<Result<(), String>>),
+    /// Replaces the state with one returned by
+    /// [`Simulation::to_json`].
+    LoadState(Value),
 }
 
+/// Sent by the simulation once it has updated the
+/// [`PositionsBuffer`].
 #[derive(Debug)]
-struct SimResults(Option<Duration>, Array2D<Vec2>);
+struct SimResults {
+    /// Calculation time of the tick, `None` when the particles
+    /// were just spawned.
+    elapsed: Option<Duration>,
+    tick_count: u64,
+    /// Particle counts, they change when particles are born or
+    /// die.
+    particle_counts: [usize; MAX_CLASSES],
+    /// See [`Simulation::order_parameter`].
+    order_parameter: f32,
+    /// Number of particle pairs that interacted during the last
+    /// tick for each pair of classes, see
+    /// [`Simulation::interaction_counts`].
+    interaction_counts: Array2D<usize>,
+    /// See [`Simulation::adjacency_counts`].
+    adjacency_counts: Array2D<usize>,
+    perf_breakdown: PerfBreakdown,
+    /// Only sent when the ui asked for them, see
+    /// [`UiEvent::SetSendVelocities`].
+    particle_velocities: Option<Array2D<Vec2>>,
+}
 
-#[derive(Debug, Clone)]
+/// Time spent in each step of the simulation.
+#[derive(Debug, Clone, Copy, Default)]
+struct PerfBreakdown {
+    /// Last spawn of the particles.
+    spawn: Duration,
+    /// Forces between particles during the last tick.
+    forces: Duration,
+    /// Part of `forces` spent on the particles of each class.
+    class_forces: [Duration; MAX_CLASSES],
+    /// External forces, separation, confinement, max density
+    /// and population changes during the last tick.
+    post_processing: Duration,
+    /// Copy of the positions to the [`PositionsBuffer`] and send
+    /// of the previous results.
+    send: Duration,
+}
+
+/// Particle positions shared between the simulation and the ui,
+/// this avoids sending a copy of all the positions every tick.
+type PositionsBuffer = Arc<RwLock<Array2D<Vec2>>>;
+
+/// Copies the positions of the particles that are actually used
+/// instead of cloning the whole array.
+fn copy_particle_positions(
+    src: &Array2D<Vec2>,
+    dst: &mut Array2D<Vec2>,
+    particle_counts: &[usize; MAX_CLASSES],
+) {
+    for (c, count) in particle_counts.iter().enumerate() {
+        for p in 0..*count {
+            dst[(c, p)] = src[(c, p)];
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 struct Param {
     force: f32,
     radius: f32,
+    /// Weak force applied past `radius`, see
+    /// [`simulation::get_partial_velocity`].
+    #[serde(default)]
+    long_range_force: f32,
 }
 impl Param {
     pub fn new(force: f32, radius: f32) -> Self {
-        Self { force, radius }
+        Self {
+            force,
+            radius,
+            long_range_force: 0.,
+        }
     }
 }
 
+/// Point (the mouse cursor) that attracts or repels all the
+/// particles.
+#[derive(Debug, Clone, Copy)]
+struct AttractorMode {
+    active: bool,
+    position: Vec2,
+    strength: f32,
+    is_repulsor: bool,
+}
+
+impl AttractorMode {
+    const INACTIVE: AttractorMode = Self {
+        active: false,
+        position: Vec2::ZERO,
+        strength: DEFAULT_ATTRACTOR_STRENGTH,
+        is_repulsor: false,
+    };
+}
+
+#[derive(Clone, Serialize, Deserialize)]
 struct SharedState {
     simulation_state: SimulationState,
     class_count: usize,
@@ -153,19 +433,31 @@ struct SharedState {
     /// Matrix containing force and radius for each particle class
     /// with respect to each other.
     param_matrix: Array2D<Param>,
+    /// Mass of the particles of each class, the forces applied
+    /// to a particle are divided by its mass.
+    class_masses: [f32; MAX_CLASSES],
+    /// Probability for each particle of a class to give birth to
+    /// a new one during a tick.
+    birth_rates: [f32; MAX_CLASSES],
+    /// Probability for each particle of a class to die during a
+    /// tick.
+    death_rates: [f32; MAX_CLASSES],
 }
 
 impl SharedState {
     fn new() -> Self {
         Self {
             simulation_state: SimulationState::Stopped,
-            class_count: MAX_CLASSES,
+            class_count: class_limit(),
             particle_counts: [0; MAX_CLASSES],
             param_matrix: Array2D::filled_with(
                 Param::new(DEFAULT_FORCE, DEFAULT_RADIUS),
                 MAX_CLASSES,
                 MAX_CLASSES,
             ),
+            class_masses: [DEFAULT_MASS; MAX_CLASSES],
+            birth_rates: [0.; MAX_CLASSES],
+            death_rates: [0.; MAX_CLASSES],
         }
     }
 }
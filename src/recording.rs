@@ -0,0 +1,116 @@
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use array2d::Array2D;
+use byteorder::{ReadBytesExt, WriteBytesExt, LE};
+use egui::Vec2;
+
+use crate::{MAX_CLASSES, MAX_PARTICLE_COUNT};
+
+/// Max number of frames in a recording, a frame of 8000
+/// particles is around 64KB.
+pub const MAX_RECORDING_LEN: usize = 10000;
+
+/// First bytes of a recording file.
+const RECORDING_MAGIC: &[u8; 4] = b"SMRC";
+
+/// Particle positions recorded every tick, only the positions of
+/// the particles that exist are stored.
+pub struct Recording {
+    pub particle_counts: [usize; MAX_CLASSES],
+    frames: Vec<Vec<Vec2>>,
+}
+
+impl Recording {
+    pub fn new(particle_counts: [usize; MAX_CLASSES]) -> Self {
+        Self {
+            particle_counts,
+            frames: Vec::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// Adds a frame, returns `false` if the recording is full.
+    pub fn push_frame(&mut self, particle_positions: &Array2D<Vec2>) -> bool {
+        if self.frames.len() >= MAX_RECORDING_LEN {
+            return false;
+        }
+        let mut frame = Vec::with_capacity(self.particle_counts.iter().sum());
+        for (c, count) in self.particle_counts.iter().enumerate() {
+            frame.extend((0..*count).map(|p| particle_positions[(c, p)]));
+        }
+        self.frames.push(frame);
+        true
+    }
+
+    /// Copies the positions of the given frame to
+    /// `particle_positions`.
+    pub fn copy_frame(&self, index: usize, particle_positions: &mut Array2D<Vec2>) {
+        let mut positions = self.frames[index].iter();
+        for (c, count) in self.particle_counts.iter().enumerate() {
+            for p in 0..*count {
+                particle_positions[(c, p)] = *positions.next().unwrap();
+            }
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(RECORDING_MAGIC)?;
+        for count in &self.particle_counts {
+            writer.write_u16::<LE>(*count as u16)?;
+        }
+        writer.write_u32::<LE>(self.frames.len() as u32)?;
+        for frame in &self.frames {
+            for pos in frame {
+                writer.write_f32::<LE>(pos.x)?;
+                writer.write_f32::<LE>(pos.y)?;
+            }
+        }
+        writer.flush()
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != RECORDING_MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a smarticles recording",
+            ));
+        }
+
+        let mut recording = Self::new([0; MAX_CLASSES]);
+        for count in &mut recording.particle_counts {
+            *count = reader.read_u16::<LE>()? as usize;
+            if *count > MAX_PARTICLE_COUNT {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "too many particles in recording",
+                ));
+            }
+        }
+        let frame_count = (reader.read_u32::<LE>()? as usize).min(MAX_RECORDING_LEN);
+        let frame_len = recording.particle_counts.iter().sum();
+        for _ in 0..frame_count {
+            let mut frame = Vec::with_capacity(frame_len);
+            for _ in 0..frame_len {
+                frame.push(Vec2::new(
+                    reader.read_f32::<LE>()?,
+                    reader.read_f32::<LE>()?,
+                ));
+            }
+            recording.frames.push(frame);
+        }
+        Ok(recording)
+    }
+}
@@ -1,28 +1,50 @@
-use std::f32::consts::TAU;
+use std::f32::consts::{PI, TAU};
+use std::fs;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::mpsc::{Receiver, Sender};
 use std::thread;
 use std::time::{Duration, Instant};
 
 use array2d::Array2D;
 use egui::Vec2;
-use log::debug;
+use log::{debug, warn};
 use rand::distributions::Open01;
 use rand::rngs::SmallRng;
+use rand::seq::SliceRandom;
 use rand::{Rng, SeedableRng};
+use rand_distr::{Distribution, Normal, Poisson};
 use rayon::prelude::*;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use serde::{de, Deserialize, Serialize};
+use serde_json::Value;
 
+use crate::basin::BasinExplorer;
+use crate::grid::CellGrid;
 use crate::{
-    SharedState, SimResults, UiEvent, UpdateSharedState, DEFAULT_FORCE, DEFAULT_RADIUS,
-    FORCE_FACTOR, MAX_CLASSES, MAX_PARTICLE_COUNT, MIN_RADIUS,
+    config, copy_particle_positions, AttractorMode, Param, PerfBreakdown, PositionsBuffer,
+    SharedState, SimResults, UiEvent, UpdateSharedState, DEFAULT_CLOSE_FORCE, DEFAULT_FORCE,
+    DEFAULT_RADIUS, DEFAULT_SPAWN_DENSITY, FORCE_FACTOR, GRAVITY_FACTOR, MAX_CLASSES, MAX_MASS,
+    MAX_PARTICLE_COUNT, MAX_POPULATION_RATE, MIN_CLASSES, MIN_MASS, MIN_RADIUS,
 };
 
 /// Min update interval in ms (when the simulation is running).
-const UPDATE_INTERVAL: Duration = Duration::from_millis(30);
+pub const UPDATE_INTERVAL: Duration = Duration::from_millis(30);
 /// Min update rate when the simulation is paused.
-const PAUSED_UPDATE_INTERVAL: Duration = Duration::from_millis(100);
+pub const PAUSED_UPDATE_INTERVAL: Duration = Duration::from_millis(100);
 
-/// Radius of the spawn area.
-const SPAWN_AREA_RADIUS: f32 = 40.;
+/// Simulated time of one tick (in seconds), a tick is meant to
+/// last [`UPDATE_INTERVAL`]. Built-in default of
+/// [`SmartConfig::dt`](crate::storage::SmartConfig::dt).
+pub const DT: f32 = 0.03;
+
+/// Radius of the area around the center of their class where
+/// new particles are born.
+const BIRTH_AREA_RADIUS: f32 = MIN_RADIUS;
+/// Side of the squares in which the max number of particles is
+/// enforced (see [`Simulation::enforce_capacity`]).
+const CAPACITY_CELL_SIZE: f32 = MIN_RADIUS;
+/// Min radius of the spawn area.
+const MIN_SPAWN_AREA_RADIUS: f32 = 10.;
 
 /// Below this radius, particles repel each other (see [`get_dv`]).
 const RAMP_START_RADIUS: f32 = MIN_RADIUS;
@@ -31,11 +53,21 @@ const RAMP_START_RADIUS: f32 = MIN_RADIUS;
 /// between particles (see [`get_dv`] second arm).
 /// The radius where the force ramp ends (see [`get_dv`] first arm).
 const RAMP_LENGTH: f32 = 10.;
-/// "Close force", see graph below.
-const CLOSE_FORCE: f32 = 20. * FORCE_FACTOR;
+/// Length of the zone past the action radius where the
+/// long-range force applies, it fades out linearly over it.
+pub const LONG_RANGE_LENGTH: f32 = 100.;
+/// Particles closer than this are moved apart after each tick, so
+/// that they never overlap (the close force alone can't prevent
+/// it when they are fast).
+const MIN_SEPARATION: f32 = 0.5;
+/// Two particles closer than this are adjacent, whatever the
+/// action radius of their classes (see
+/// [`Simulation::adjacency_counts`]).
+pub const ADJACENCY_RANGE: f32 = RAMP_START_RADIUS;
 
 // I made a graph of the force with respect to distance in
-// order to explain the constants above (it might not help at all):
+// order to explain the constants above (it might not help at all),
+// the close force is set from the ui (see `Simulation::close_force`):
 //
 //
 //                   force ^
@@ -55,49 +87,402 @@ const CLOSE_FORCE: f32 = 20. * FORCE_FACTOR;
 //                         |            ----/       |                 |
 //                         |       ----/            |                 |
 //                         |  ----/         RAMP_START_RADIUS     RAMP_START_RADIUS + RAMP_LENGTH
-//            CLOSE_FORCE  |-/
+//            close force  |-/
 //                         |
 //                         |
 //                         |
 //                         |
 //
 
-const DAMPING_FACTOR: f32 = 0.6;
+/// Built-in default of
+/// [`SmartConfig::damping_factor`](crate::storage::SmartConfig::damping_factor).
+pub const DAMPING_FACTOR: f32 = 0.6;
 
-#[derive(PartialEq)]
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
 pub enum SimulationState {
     Stopped,
     Paused,
     Running,
 }
 
+/// Shape the particles are confined in, centered on the origin.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum BoundaryShape {
+    None,
+    /// Radius.
+    Circle(f32),
+    /// Width and height.
+    Rectangle(f32, f32),
+    /// Width and height.
+    Ellipse(f32, f32),
+}
+
+impl BoundaryShape {
+    pub fn name(&self) -> &'static str {
+        match self {
+            BoundaryShape::None => "none",
+            BoundaryShape::Circle(_) => "circle",
+            BoundaryShape::Rectangle(_, _) => "rectangle",
+            BoundaryShape::Ellipse(_, _) => "ellipse",
+        }
+    }
+}
+
+/// Region the particles are spawned in, centered on the origin.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum SpawnShape {
+    /// Uniformly in a disk whose size depends on the spawn
+    /// density.
+    #[default]
+    Disk,
+    /// At a random distance between the two radii.
+    Ring {
+        inner_radius: f32,
+        outer_radius: f32,
+    },
+    /// On a grid with `cols` columns, class after class.
+    Grid { cols: usize },
+    /// Each class in a gaussian cluster, the classes are spread
+    /// over `num_clusters` clusters.
+    Clustered { num_clusters: usize },
+}
+
+impl SpawnShape {
+    pub fn name(&self) -> &'static str {
+        match self {
+            SpawnShape::Disk => "disk",
+            SpawnShape::Ring { .. } => "ring",
+            SpawnShape::Grid { .. } => "grid",
+            SpawnShape::Clustered { .. } => "clustered",
+        }
+    }
+}
+
+/// Everything [`Simulation::to_json`] saves, the attractor is
+/// left out since it follows the mouse.
+#[derive(Serialize, Deserialize)]
+pub struct SimulationSnapshot {
+    pub shared: SharedState,
+    pub particle_positions: Array2D<Vec2>,
+    pub particle_velocities: Array2D<Vec2>,
+    pub tick_count: u64,
+    pub temperature: f32,
+    pub gravity: Vec2,
+    pub vortex_strength: f32,
+    pub boundary: BoundaryShape,
+    pub restitution: f32,
+    pub max_particles_per_cell: Option<usize>,
+    #[serde(default = "default_spawn_density")]
+    pub spawn_density: f32,
+    #[serde(default)]
+    pub spawn_shape: SpawnShape,
+    #[serde(default = "default_close_force")]
+    pub close_force: f32,
+    #[serde(default)]
+    pub frozen_classes: [bool; MAX_CLASSES],
+}
+
+impl SimulationSnapshot {
+    /// Checks that the arrays and counts fit in the simulation and
+    /// that the class settings are within the ranges of the ui, a
+    /// state edited by hand could otherwise make it panic or send
+    /// the particles to infinity.
+    pub fn validate(&self) -> Result<(), &'static str> {
+        let has_simulation_size = |array: &Array2D<Vec2>| {
+            array.num_rows() == MAX_CLASSES && array.num_columns() == MAX_PARTICLE_COUNT
+        };
+        if !has_simulation_size(&self.particle_positions)
+            || !has_simulation_size(&self.particle_velocities)
+            || self.shared.param_matrix.num_rows() != MAX_CLASSES
+            || self.shared.param_matrix.num_columns() != MAX_CLASSES
+            || self
+                .shared
+                .particle_counts
+                .iter()
+                .any(|count| *count > MAX_PARTICLE_COUNT)
+        {
+            return Err("the state does not match the size of the simulation");
+        }
+        if !(MIN_CLASSES..=MAX_CLASSES).contains(&self.shared.class_count) {
+            return Err("invalid class count");
+        }
+        let in_range = |values: &[f32], min: f32, max: f32| {
+            values.iter().all(|value| (min..=max).contains(value))
+        };
+        if !in_range(&self.shared.class_masses, MIN_MASS, MAX_MASS) {
+            return Err("invalid class mass");
+        }
+        if !in_range(&self.shared.birth_rates, 0., MAX_POPULATION_RATE)
+            || !in_range(&self.shared.death_rates, 0., MAX_POPULATION_RATE)
+        {
+            return Err("invalid birth or death rate");
+        }
+        Ok(())
+    }
+}
+
+fn default_spawn_density() -> f32 {
+    DEFAULT_SPAWN_DENSITY
+}
+
+fn default_close_force() -> f32 {
+    DEFAULT_CLOSE_FORCE
+}
+
 pub struct Simulation {
     shared: SharedState,
 
     particle_positions: Array2D<Vec2>,
     particle_velocities: Array2D<Vec2>,
+    /// Buffer the new positions and velocities are collected into
+    /// before being applied (reused to avoid allocating every
+    /// tick).
+    new_states: Vec<(Vec2, Vec2)>,
+    /// Grids used by [`Simulation::separate_particles`] and
+    /// [`Simulation::enforce_capacity`], kept between the ticks.
+    separation_grid: CellGrid,
+    capacity_grid: CellGrid,
+
+    /// Number of ticks since the particles were spawned.
+    tick_count: u64,
+    /// Number of particle pairs of classes (i, j) that were
+    /// within the action radius of the param (i, j) during the
+    /// last tick.
+    interaction_counts: Array2D<usize>,
+    /// Number of pairs of particles of classes (i, j) closer than
+    /// [`ADJACENCY_RANGE`] during the last tick, only computed
+    /// when `count_adjacencies` is set.
+    adjacency_counts: Array2D<usize>,
+    perf_breakdown: PerfBreakdown,
+
+    positions_buffer: PositionsBuffer,
+
+    /// Strength of the random thermal noise added to the
+    /// velocities every tick.
+    temperature: f32,
+    rand: SmallRng,
+    /// Constant force applied to every particle.
+    gravity: Vec2,
+    attractor: AttractorMode,
+    /// Strength of the rotation around the origin, negative is
+    /// clockwise.
+    vortex_strength: f32,
+
+    boundary: BoundaryShape,
+    /// Fraction of the velocity kept by the particles bouncing
+    /// on the boundary.
+    restitution: f32,
+
+    /// Max number of particles in a square of side
+    /// [`CAPACITY_CELL_SIZE`], the excess particles are moved to
+    /// the center of the simulation.
+    max_particles_per_cell: Option<usize>,
+
+    /// Number of particles per unit of area when spawning.
+    spawn_density: f32,
+    spawn_shape: SpawnShape,
+
+    /// Repulsion between particles closer than
+    /// [`RAMP_START_RADIUS`], see [`get_partial_velocity`].
+    close_force: f32,
+
+    /// Whether the velocities are sent with the results, they are
+    /// only copied when the ui needs them.
+    send_velocities: bool,
+    /// Whether the adjacent pairs are counted, only the ui needs
+    /// them.
+    count_adjacencies: bool,
+
+    /// Classes whose particles don't move, for debugging.
+    frozen_classes: [bool; MAX_CLASSES],
+
+    basin_explorer: Option<BasinExplorer>,
+
+    /// Thread pool used to move the particles, the global one is
+    /// used when `None`.
+    thread_pool: Option<ThreadPool>,
 
     sim_send: Sender<SimResults>,
     ui_rcv: Receiver<UiEvent>,
 }
 
 impl Simulation {
-    pub fn new(sim_send: Sender<SimResults>, ui_rcv: Receiver<UiEvent>) -> Self {
+    pub fn builder() -> SimulationBuilder {
+        SimulationBuilder {
+            shared: SharedState::new(),
+        }
+    }
+
+    pub fn new(
+        sim_send: Sender<SimResults>,
+        ui_rcv: Receiver<UiEvent>,
+        positions_buffer: PositionsBuffer,
+    ) -> Self {
         Self {
             shared: SharedState::new(),
 
             particle_positions: Array2D::filled_with(Vec2::ZERO, MAX_CLASSES, MAX_PARTICLE_COUNT),
             particle_velocities: Array2D::filled_with(Vec2::ZERO, MAX_CLASSES, MAX_PARTICLE_COUNT),
+            new_states: Vec::with_capacity(MAX_PARTICLE_COUNT),
+            separation_grid: CellGrid::new(MIN_SEPARATION),
+            capacity_grid: CellGrid::new(CAPACITY_CELL_SIZE),
+
+            tick_count: 0,
+            interaction_counts: Array2D::filled_with(0, MAX_CLASSES, MAX_CLASSES),
+            adjacency_counts: Array2D::filled_with(0, MAX_CLASSES, MAX_CLASSES),
+            perf_breakdown: PerfBreakdown::default(),
+
+            positions_buffer,
+
+            temperature: 0.,
+            rand: SmallRng::from_entropy(),
+            gravity: Vec2::ZERO,
+            attractor: AttractorMode::INACTIVE,
+            vortex_strength: 0.,
+
+            boundary: BoundaryShape::None,
+            restitution: 0.,
+
+            max_particles_per_cell: None,
+
+            spawn_density: DEFAULT_SPAWN_DENSITY,
+            spawn_shape: SpawnShape::Disk,
+
+            close_force: DEFAULT_CLOSE_FORCE,
+
+            send_velocities: false,
+            count_adjacencies: false,
+
+            frozen_classes: [false; MAX_CLASSES],
+
+            basin_explorer: None,
+
+            thread_pool: None,
 
             sim_send,
             ui_rcv,
         }
     }
 
+    /// Creates a simulation with the same settings and spawns its
+    /// particles.
+    pub fn fork(
+        &self,
+        sim_send: Sender<SimResults>,
+        ui_rcv: Receiver<UiEvent>,
+        positions_buffer: PositionsBuffer,
+    ) -> Simulation {
+        let mut simulation =
+            Simulation::from_json(self.to_json(), sim_send, ui_rcv, positions_buffer).unwrap();
+        simulation.attractor = self.attractor;
+        simulation.particle_velocities =
+            Array2D::filled_with(Vec2::ZERO, MAX_CLASSES, MAX_PARTICLE_COUNT);
+        simulation.spawn();
+        simulation
+    }
+
+    /// Serializes the state of the simulation (see
+    /// [`SimulationSnapshot`]), the channels, the random number
+    /// generator and the thread pool are not part of it.
+    pub fn to_json(&self) -> Value {
+        serde_json::to_value(SimulationSnapshot {
+            shared: self.shared.to_owned(),
+            particle_positions: self.particle_positions.to_owned(),
+            particle_velocities: self.particle_velocities.to_owned(),
+            tick_count: self.tick_count,
+            temperature: self.temperature,
+            gravity: self.gravity,
+            vortex_strength: self.vortex_strength,
+            boundary: self.boundary,
+            restitution: self.restitution,
+            max_particles_per_cell: self.max_particles_per_cell,
+            spawn_density: self.spawn_density,
+            close_force: self.close_force,
+            spawn_shape: self.spawn_shape,
+            frozen_classes: self.frozen_classes,
+        })
+        .unwrap()
+    }
+
+    /// Creates a simulation from a state returned by
+    /// [`Simulation::to_json`].
+    pub fn from_json(
+        value: Value,
+        sim_send: Sender<SimResults>,
+        ui_rcv: Receiver<UiEvent>,
+        positions_buffer: PositionsBuffer,
+    ) -> serde_json::Result<Simulation> {
+        let mut simulation = Simulation::new(sim_send, ui_rcv, positions_buffer);
+        simulation.load_json(value)?;
+        Ok(simulation)
+    }
+
+    fn load_json(&mut self, value: Value) -> serde_json::Result<()> {
+        let snapshot: SimulationSnapshot = serde_json::from_value(value)?;
+        snapshot.validate().map_err(de::Error::custom)?;
+
+        self.shared = snapshot.shared;
+        self.particle_positions = snapshot.particle_positions;
+        self.particle_velocities = snapshot.particle_velocities;
+        self.tick_count = snapshot.tick_count;
+        self.temperature = snapshot.temperature;
+        self.gravity = snapshot.gravity;
+        self.vortex_strength = snapshot.vortex_strength;
+        self.boundary = snapshot.boundary;
+        self.restitution = snapshot.restitution;
+        self.max_particles_per_cell = snapshot.max_particles_per_cell;
+        self.spawn_density = snapshot.spawn_density;
+        self.close_force = snapshot.close_force;
+        self.spawn_shape = snapshot.spawn_shape;
+        self.frozen_classes = snapshot.frozen_classes;
+        Ok(())
+    }
+
     pub fn update(&mut self) -> bool {
+        if !self.handle_events() {
+            return false;
+        }
+
+        let running = self.shared.simulation_state == SimulationState::Running;
+        if let Some(basin_explorer) = &mut self.basin_explorer {
+            match &self.thread_pool {
+                Some(thread_pool) => thread_pool.install(|| basin_explorer.update(running)),
+                None => basin_explorer.update(running),
+            }
+        }
+
+        if running {
+            let start_time = Instant::now();
+            self.step_n(1);
+            let elapsed = start_time.elapsed();
+            self.send_positions(Some(elapsed));
+
+            debug!(
+                "calculation took {:?} (sim time: {:.1}s)\n{}",
+                elapsed,
+                self.elapsed_time(),
+                "#".to_string().repeat(elapsed.as_millis() as usize)
+            );
+            if elapsed < UPDATE_INTERVAL {
+                thread::sleep(UPDATE_INTERVAL - elapsed);
+            }
+        } else {
+            debug!("simulation paused, update interval reduced");
+            thread::sleep(PAUSED_UPDATE_INTERVAL);
+        }
+
+        true
+    }
+
+    /// Applies the events sent by the ui, returns `false` if the
+    /// simulation should stop.
+    pub fn handle_events(&mut self) -> bool {
         let events = self.ui_rcv.try_iter().collect::<Vec<_>>();
         debug!("Received events {:?}", events);
         for event in events {
+            if let Some(basin_explorer) = &self.basin_explorer {
+                basin_explorer.forward(&event);
+            }
             match event {
                 UiEvent::Play => self.play(),
                 UiEvent::Pause => self.pause(),
@@ -111,74 +496,545 @@ impl Simulation {
                 UiEvent::ParamsUpdate(params) => self.shared.param_matrix = params,
                 UiEvent::ClassCountUpdate(class_count) => self.shared.class_count = class_count,
                 UiEvent::ParticleCountsUpdate(particle_counts) => {
-                    self.shared.particle_counts = particle_counts
+                    self.shared.particle_counts = particle_counts;
+                    self.clamp_particle_counts();
+                }
+                UiEvent::ClassMassesUpdate(class_masses) => self.shared.class_masses = class_masses,
+                UiEvent::PopulationRatesUpdate(birth_rates, death_rates) => {
+                    self.shared.birth_rates = birth_rates;
+                    self.shared.death_rates = death_rates;
+                }
+                UiEvent::SetThreadCount(thread_count) => {
+                    match ThreadPoolBuilder::new().num_threads(thread_count).build() {
+                        Ok(thread_pool) => self.thread_pool = Some(thread_pool),
+                        Err(err) => warn!("failed to build thread pool: {}", err),
+                    }
+                }
+                UiEvent::SetTemperature(temperature) => self.temperature = temperature,
+                UiEvent::SetGravity(gravity) => self.gravity = gravity,
+                UiEvent::SetAttractor(attractor) => self.attractor = attractor,
+                UiEvent::SetVortexStrength(vortex_strength) => {
+                    self.vortex_strength = vortex_strength
+                }
+                UiEvent::SetBoundary(boundary, restitution) => {
+                    self.boundary = boundary;
+                    self.restitution = restitution;
                 }
+                UiEvent::SetMaxParticlesPerCell(max_particles_per_cell) => {
+                    self.max_particles_per_cell = max_particles_per_cell
+                }
+                UiEvent::SetSpawnDensity(spawn_density) => self.spawn_density = spawn_density,
+                UiEvent::SetSpawnShape(spawn_shape) => self.spawn_shape = spawn_shape,
+                UiEvent::SetCloseForce(close_force) => self.close_force = close_force,
+                UiEvent::SetSendVelocities(send_velocities) => {
+                    self.send_velocities = send_velocities
+                }
+                UiEvent::SetCountAdjacencies(count_adjacencies) => {
+                    self.count_adjacencies = count_adjacencies
+                }
+                UiEvent::InjectParticle(c, pos) => self.inject_particle(c, pos),
+                UiEvent::DeleteParticle(c, p) => self.delete_particle(c, p),
+                UiEvent::FreezeClass(c) => {
+                    self.frozen_classes[c] = true;
+                    for p in 0..self.shared.particle_counts[c] {
+                        self.particle_velocities[(c, p)] = Vec2::ZERO;
+                    }
+                }
+                UiEvent::UnfreezeClass(c) => self.frozen_classes[c] = false,
+                UiEvent::Step(tick_count) => {
+                    self.step_n(tick_count);
+                    self.send_positions(None);
+                }
+                UiEvent::StartBasinExplorer(run_count, buffer) => {
+                    self.basin_explorer = Some(BasinExplorer::new(self, run_count, buffer))
+                }
+                UiEvent::StopBasinExplorer => self.basin_explorer = None,
+                UiEvent::SaveState(path, result_send) => {
+                    let json = serde_json::to_string_pretty(&self.to_json()).unwrap();
+                    // The ui may have been closed in the meantime.
+                    let _ = result_send.send(fs::write(path, json).map_err(|err| err.to_string()));
+                }
+                UiEvent::LoadState(value) => match self.load_json(value) {
+                    Ok(_) => self.send_positions(None),
+                    Err(err) => warn!("failed to load state: {}", err),
+                },
             }
         }
+        true
+    }
 
-        if self.shared.simulation_state == SimulationState::Running {
-            let start_time = Instant::now();
-            self.move_particles();
-            let elapsed = start_time.elapsed();
-            self.sim_send
-                .send(SimResults(
-                    Some(elapsed),
-                    self.particle_positions.to_owned(),
-                ))
-                .unwrap();
+    pub fn particle_positions(&self) -> &Array2D<Vec2> {
+        &self.particle_positions
+    }
 
-            debug!(
-                "calculation took {:?}\n{}",
-                elapsed,
-                "#".to_string().repeat(elapsed.as_millis() as usize)
-            );
-            if elapsed < UPDATE_INTERVAL {
-                thread::sleep(UPDATE_INTERVAL - elapsed);
+    pub fn particle_counts(&self) -> [usize; MAX_CLASSES] {
+        self.shared.particle_counts
+    }
+
+    /// Simulated time since the particles were spawned (in
+    /// seconds).
+    pub fn elapsed_time(&self) -> f32 {
+        self.tick_count as f32 * config().dt
+    }
+
+    /// Number of particles, including the ones of the disabled
+    /// classes.
+    pub fn particle_count(&self) -> usize {
+        self.shared.particle_counts.iter().sum()
+    }
+
+    /// Number of particles of the enabled classes.
+    pub fn enabled_particle_count(&self) -> usize {
+        self.shared.particle_counts[..self.shared.class_count]
+            .iter()
+            .sum()
+    }
+
+    /// Moves the particles `n` ticks ahead, nothing is sent to the
+    /// ui and there is no wait between the ticks.
+    pub fn step_n(&mut self, n: usize) {
+        match self.thread_pool.take() {
+            Some(thread_pool) => {
+                thread_pool.install(|| (0..n).for_each(|_| self.move_particles()));
+                self.thread_pool = Some(thread_pool);
             }
-        } else {
-            debug!("simulation paused, update interval reduced");
-            thread::sleep(PAUSED_UPDATE_INTERVAL);
+            None => (0..n).for_each(|_| self.move_particles()),
         }
-
-        true
     }
 
-    fn move_particles(&mut self) {
+    pub fn move_particles(&mut self) {
+        self.tick_count += 1;
+        let start_time = Instant::now();
+
+        self.perf_breakdown.class_forces = [Duration::ZERO; MAX_CLASSES];
         for c1 in 0..self.shared.class_count {
+            let class_start_time = Instant::now();
             for c2 in 0..self.shared.class_count {
                 let param = &self.shared.param_matrix[(c1, c2)];
                 let force = -param.force * FORCE_FACTOR;
+                let long_range_force = -param.long_range_force * FORCE_FACTOR;
+                let close_force = self.close_force * FORCE_FACTOR;
                 let radius = param.radius;
+                let mass = self.shared.class_masses[c1];
+                let damping_factor = config().damping_factor;
+                let count_adjacencies = self.count_adjacencies;
+                let interaction_count = AtomicUsize::new(0);
+                let adjacency_count = AtomicUsize::new(0);
 
                 (0..self.shared.particle_counts[c1])
                     .into_par_iter()
                     .map(|p1| {
                         let mut f = Vec2::ZERO;
+                        let mut interactions = 0;
+                        let mut adjacencies = 0;
 
                         let pos = self.particle_positions[(c1, p1)];
                         let vel = self.particle_velocities[(c1, p1)];
                         for p2 in 0..self.shared.particle_counts[c2] {
-                            let other_pos = self.particle_positions[(c2, p2)];
-                            f += get_partial_velocity(other_pos - pos, radius, force);
+                            let distance = self.particle_positions[(c2, p2)] - pos;
+                            let distance_sq = distance.length_sq();
+                            let is_other = c1 != c2 || p1 != p2;
+                            if distance_sq < radius * radius && is_other {
+                                interactions += 1;
+                            }
+                            // Pairs of the same class are only counted
+                            // from their first particle.
+                            if count_adjacencies
+                                && distance_sq < ADJACENCY_RANGE * ADJACENCY_RANGE
+                                && (c1 != c2 || p2 > p1)
+                            {
+                                adjacencies += 1;
+                            }
+                            f += get_partial_velocity(
+                                distance,
+                                radius,
+                                force,
+                                long_range_force,
+                                close_force,
+                            );
                         }
+                        f /= mass;
+                        interaction_count.fetch_add(interactions, Ordering::Relaxed);
+                        adjacency_count.fetch_add(adjacencies, Ordering::Relaxed);
 
                         // friction force
-                        f -= vel * DAMPING_FACTOR;
+                        f -= vel * damping_factor;
 
                         let new_vel = vel + f;
                         let new_pos = pos + vel;
 
                         (new_pos, new_vel)
                     })
-                    .collect::<Vec<(Vec2, Vec2)>>()
-                    .iter()
-                    .enumerate()
-                    .for_each(|(p1, (new_pos, new_vel))| {
+                    .collect_into_vec(&mut self.new_states);
+
+                if !self.frozen_classes[c1] {
+                    for (p1, (new_pos, new_vel)) in self.new_states.iter().enumerate() {
                         self.particle_positions[(c1, p1)] = *new_pos;
                         self.particle_velocities[(c1, p1)] = *new_vel;
-                    });
+                    }
+                }
+                self.interaction_counts[(c1, c2)] = interaction_count.into_inner();
+                self.adjacency_counts[(c1, c2)] = adjacency_count.into_inner();
+            }
+            self.perf_breakdown.class_forces[c1] = class_start_time.elapsed();
+        }
+        self.perf_breakdown.forces = start_time.elapsed();
+        let start_time = Instant::now();
+
+        // Disabled classes don't interact.
+        for c1 in 0..MAX_CLASSES {
+            for c2 in 0..MAX_CLASSES {
+                if c1 >= self.shared.class_count || c2 >= self.shared.class_count {
+                    self.interaction_counts[(c1, c2)] = 0;
+                    self.adjacency_counts[(c1, c2)] = 0;
+                }
             }
         }
+
+        if self.temperature > 0.
+            || self.gravity != Vec2::ZERO
+            || self.attractor.active
+            || self.vortex_strength != 0.
+        {
+            self.apply_external_forces();
+        }
+        self.separate_particles();
+        if self.boundary != BoundaryShape::None {
+            self.confine_particles();
+        }
+        if let Some(max_particles_per_cell) = self.max_particles_per_cell {
+            self.enforce_capacity(max_particles_per_cell);
+        }
+        if self.shared.birth_rates.iter().any(|r| *r > 0.)
+            || self.shared.death_rates.iter().any(|r| *r > 0.)
+        {
+            self.update_population();
+        }
+        self.perf_breakdown.post_processing = start_time.elapsed();
+    }
+
+    /// Moves apart the pairs of particles closer than
+    /// [`MIN_SEPARATION`], each one by half of the missing
+    /// distance (or all of it when the other one is frozen).
+    fn separate_particles(&mut self) {
+        let positions = &self.particle_positions;
+        self.separation_grid
+            .fill(enabled_particles(&self.shared, positions));
+
+        for (cell, particles) in self.separation_grid.cells() {
+            for &(c1, p1) in particles {
+                for (c2, p2) in self.separation_grid.neighbors(cell) {
+                    // Each pair is only handled once.
+                    if (c2, p2) <= (c1, p1) {
+                        continue;
+                    }
+                    let distance =
+                        self.particle_positions[(c2, p2)] - self.particle_positions[(c1, p1)];
+                    let r = distance.length();
+                    if r >= MIN_SEPARATION {
+                        continue;
+                    }
+                    let direction = if r > 0. { distance / r } else { Vec2::X };
+                    let overlap = MIN_SEPARATION - r;
+                    match (self.frozen_classes[c1], self.frozen_classes[c2]) {
+                        (false, false) => {
+                            self.particle_positions[(c1, p1)] -= direction * overlap / 2.;
+                            self.particle_positions[(c2, p2)] += direction * overlap / 2.;
+                        }
+                        (false, true) => self.particle_positions[(c1, p1)] -= direction * overlap,
+                        (true, false) => self.particle_positions[(c2, p2)] += direction * overlap,
+                        (true, true) => (),
+                    }
+                }
+            }
+        }
+    }
+
+    /// Moves randomly chosen particles out of the cells that hold
+    /// more than `max_particles_per_cell` particles, to the center
+    /// of the simulation.
+    fn enforce_capacity(&mut self, max_particles_per_cell: usize) {
+        let particle_count = self.enabled_particle_count();
+        if particle_count == 0 {
+            return;
+        }
+        let positions = &self.particle_positions;
+        let centroid = enabled_particles(&self.shared, positions)
+            .fold(Vec2::ZERO, |sum, (_, pos)| sum + pos)
+            / particle_count as f32;
+
+        let frozen_classes = self.frozen_classes;
+        self.capacity_grid.fill(
+            enabled_particles(&self.shared, positions).filter(|((c, _), _)| !frozen_classes[*c]),
+        );
+
+        for particles in self.capacity_grid.cells_mut() {
+            if particles.len() <= max_particles_per_cell {
+                continue;
+            }
+            particles.shuffle(&mut self.rand);
+            for &(c, p) in &particles[max_particles_per_cell..] {
+                // Spread around the center so they don't all land
+                // on the same point.
+                self.particle_positions[(c, p)] = centroid
+                    + MIN_SPAWN_AREA_RADIUS
+                        * Vec2::angled(TAU * self.rand.sample::<f32, _>(Open01))
+                        * self.rand.sample::<f32, _>(Open01);
+                self.particle_velocities[(c, p)] = Vec2::ZERO;
+            }
+        }
+    }
+
+    /// Randomly kills particles and spawns new ones near the
+    /// center of their class.
+    fn update_population(&mut self) {
+        for c in 0..self.shared.class_count {
+            // Particles of frozen classes stay where they were
+            // pinned.
+            if self.frozen_classes[c] {
+                continue;
+            }
+
+            let mut count = self.shared.particle_counts[c];
+
+            let death_rate = self.shared.death_rates[c] as f64;
+            if death_rate > 0. {
+                let mut p = 0;
+                while p < count {
+                    if self.rand.gen_bool(death_rate) {
+                        // The last particle takes the place of the
+                        // dead one.
+                        count -= 1;
+                        self.particle_positions[(c, p)] = self.particle_positions[(c, count)];
+                        self.particle_velocities[(c, p)] = self.particle_velocities[(c, count)];
+                    } else {
+                        p += 1;
+                    }
+                }
+            }
+
+            let expected_births = self.shared.birth_rates[c] * count as f32;
+            if expected_births > 0. {
+                let centroid = (0..count)
+                    .fold(Vec2::ZERO, |sum, p| sum + self.particle_positions[(c, p)])
+                    / count as f32;
+                let births = Poisson::new(expected_births)
+                    .unwrap()
+                    .sample(&mut self.rand) as usize;
+                for _ in 0..births.min(MAX_PARTICLE_COUNT - count) {
+                    self.particle_positions[(c, count)] = centroid
+                        + BIRTH_AREA_RADIUS
+                            * Vec2::angled(TAU * self.rand.sample::<f32, _>(Open01))
+                            * self.rand.sample::<f32, _>(Open01);
+                    self.particle_velocities[(c, count)] = Vec2::ZERO;
+                    count += 1;
+                }
+            }
+
+            self.shared.particle_counts[c] = count;
+        }
+    }
+
+    /// Makes sure the particle counts fit in the position and
+    /// velocity arrays, counts set by the ui always do but the
+    /// ones coming from other places might not.
+    fn clamp_particle_counts(&mut self) {
+        for (c, count) in self.shared.particle_counts.iter_mut().enumerate() {
+            if *count > MAX_PARTICLE_COUNT {
+                warn!(
+                    "particle count of class {} clamped from {} to {}",
+                    c, count, MAX_PARTICLE_COUNT
+                );
+                *count = MAX_PARTICLE_COUNT;
+            }
+        }
+    }
+
+    fn inject_particle(&mut self, c: usize, pos: Vec2) {
+        let count = self.shared.particle_counts[c];
+        if count >= MAX_PARTICLE_COUNT {
+            return;
+        }
+        self.particle_positions[(c, count)] = pos;
+        self.particle_velocities[(c, count)] = Vec2::ZERO;
+        self.shared.particle_counts[c] += 1;
+        self.send_positions(None);
+    }
+
+    fn delete_particle(&mut self, c: usize, p: usize) {
+        let count = self.shared.particle_counts[c];
+        if p >= count {
+            return;
+        }
+        // The last particle takes the place of the deleted one.
+        self.particle_positions[(c, p)] = self.particle_positions[(c, count - 1)];
+        self.particle_velocities[(c, p)] = self.particle_velocities[(c, count - 1)];
+        self.shared.particle_counts[c] -= 1;
+        self.send_positions(None);
+    }
+
+    /// Moves the particles that are outside the boundary back onto
+    /// it and makes them bounce.
+    fn confine_particles(&mut self) {
+        for c in 0..self.shared.class_count {
+            if self.frozen_classes[c] {
+                continue;
+            }
+            for p in 0..self.shared.particle_counts[c] {
+                let pos = &mut self.particle_positions[(c, p)];
+                let vel = &mut self.particle_velocities[(c, p)];
+
+                // Outward normal of the boundary where the particle
+                // was put back.
+                let normal = match self.boundary {
+                    BoundaryShape::None => continue,
+                    BoundaryShape::Circle(radius) => {
+                        if pos.length() <= radius {
+                            continue;
+                        }
+                        *pos = pos.normalized() * radius;
+                        pos.normalized()
+                    }
+                    BoundaryShape::Rectangle(width, height) => {
+                        let half_size = Vec2::new(width, height) / 2.;
+                        let clamped = pos.clamp(-half_size, half_size);
+                        if clamped == *pos {
+                            continue;
+                        }
+                        let normal = *pos - clamped;
+                        *pos = clamped;
+                        normal.normalized()
+                    }
+                    BoundaryShape::Ellipse(width, height) => {
+                        let semi_axes = Vec2::new(width, height) / 2.;
+                        let k = (*pos / semi_axes).length();
+                        if k <= 1. {
+                            continue;
+                        }
+                        *pos = *pos / k;
+                        (*pos / (semi_axes * semi_axes)).normalized()
+                    }
+                };
+
+                let outward_speed = vel.dot(normal);
+                if outward_speed > 0. {
+                    *vel -= (1. + self.restitution) * outward_speed * normal;
+                }
+            }
+        }
+    }
+
+    /// Adds the forces that don't come from other particles
+    /// (gravity, the attractor, the vortex and a random gaussian
+    /// thermal kick) to the velocity of every particle.
+    fn apply_external_forces(&mut self) {
+        let noise = Normal::new(0., self.temperature * FORCE_FACTOR * config().dt.sqrt()).unwrap();
+        let gravity = self.gravity * GRAVITY_FACTOR;
+        for c in 0..self.shared.class_count {
+            if self.frozen_classes[c] {
+                continue;
+            }
+            for p in 0..self.shared.particle_counts[c] {
+                let mut f = gravity;
+                if self.vortex_strength != 0. {
+                    // The y axis points down, so (y, -x) is
+                    // counterclockwise on screen.
+                    let pos = self.particle_positions[(c, p)];
+                    f += self.vortex_strength * FORCE_FACTOR * Vec2::new(pos.y, -pos.x)
+                        / (pos.length() + 1.);
+                }
+                if self.attractor.active {
+                    let distance = self.attractor.position - self.particle_positions[(c, p)];
+                    let r = distance.length();
+                    if r > 0. {
+                        let sign: f32 = if self.attractor.is_repulsor { -1. } else { 1. };
+                        f += sign
+                            * distance.normalized()
+                            * self.attractor.strength
+                            * FORCE_FACTOR
+                            * RAMP_START_RADIUS
+                            / r.max(RAMP_START_RADIUS);
+                    }
+                }
+                if self.temperature > 0. {
+                    f += Vec2::new(noise.sample(&mut self.rand), noise.sample(&mut self.rand));
+                }
+                self.particle_velocities[(c, p)] += f;
+            }
+        }
+    }
+
+    /// How aligned the velocities of the particles are: 1 when
+    /// they all move in the same direction (flocking) and close to
+    /// 0 when they move randomly (like the Vicsek order
+    /// parameter).
+    pub fn order_parameter(&self) -> f32 {
+        let mut direction_sum = Vec2::ZERO;
+        let mut moving_count = 0;
+        for c in 0..self.shared.class_count {
+            for p in 0..self.shared.particle_counts[c] {
+                let vel = self.particle_velocities[(c, p)];
+                if vel != Vec2::ZERO {
+                    direction_sum += vel.normalized();
+                    moving_count += 1;
+                }
+            }
+        }
+        if moving_count == 0 {
+            0.
+        } else {
+            direction_sum.length() / moving_count as f32
+        }
+    }
+
+    /// Mean distance between two particles of the enabled classes,
+    /// low when the particles are packed together.
+    pub fn mean_particle_distance(&self) -> f32 {
+        let positions = (0..self.shared.class_count)
+            .flat_map(|c| (0..self.shared.particle_counts[c]).map(move |p| (c, p)))
+            .map(|particle| self.particle_positions[particle])
+            .collect::<Vec<_>>();
+        let pair_count = positions.len() * positions.len().saturating_sub(1) / 2;
+        if pair_count == 0 {
+            return 0.;
+        }
+        let distance_sum = positions
+            .par_iter()
+            .enumerate()
+            .map(|(i, pos)| {
+                positions[i + 1..]
+                    .iter()
+                    .map(|other| (*other - *pos).length())
+                    .sum::<f32>()
+            })
+            .sum::<f32>();
+        distance_sum / pair_count as f32
+    }
+
+    /// Copies the positions to the [`PositionsBuffer`] and lets
+    /// the ui know they changed.
+    fn send_positions(&mut self, elapsed: Option<Duration>) {
+        let start_time = Instant::now();
+        copy_particle_positions(
+            &self.particle_positions,
+            &mut self.positions_buffer.write().unwrap(),
+            &self.shared.particle_counts,
+        );
+        self.sim_send
+            .send(SimResults {
+                elapsed,
+                tick_count: self.tick_count,
+                particle_counts: self.shared.particle_counts,
+                order_parameter: self.order_parameter(),
+                interaction_counts: self.interaction_counts.to_owned(),
+                adjacency_counts: self.adjacency_counts.to_owned(),
+                perf_breakdown: self.perf_breakdown,
+                particle_velocities: self
+                    .send_velocities
+                    .then(|| self.particle_velocities.to_owned()),
+            })
+            .unwrap();
+        self.perf_breakdown.send = start_time.elapsed();
     }
 
     fn reset_particles(&mut self) {
@@ -190,6 +1046,37 @@ impl Simulation {
     }
 }
 
+/// Builds a [`Simulation`] with some of its state already set,
+/// see [`Simulation::builder`].
+pub struct SimulationBuilder {
+    shared: SharedState,
+}
+
+impl SimulationBuilder {
+    pub fn particle_counts(mut self, particle_counts: [usize; MAX_CLASSES]) -> Self {
+        self.shared.particle_counts = particle_counts;
+        self
+    }
+
+    pub fn param_matrix(mut self, param_matrix: Array2D<Param>) -> Self {
+        self.shared.param_matrix = param_matrix;
+        self
+    }
+
+    /// Creates the simulation and spawns the particles.
+    pub fn build(
+        self,
+        sim_send: Sender<SimResults>,
+        ui_rcv: Receiver<UiEvent>,
+        positions_buffer: PositionsBuffer,
+    ) -> Simulation {
+        let mut simulation = Simulation::new(sim_send, ui_rcv, positions_buffer);
+        simulation.shared = self.shared;
+        simulation.spawn();
+        simulation
+    }
+}
+
 impl UpdateSharedState for Simulation {
     fn play(&mut self) {
         self.shared.simulation_state = SimulationState::Running;
@@ -211,31 +1098,124 @@ impl UpdateSharedState for Simulation {
         }
     }
     fn spawn(&mut self) {
+        let start_time = Instant::now();
+        self.clamp_particle_counts();
         self.reset_particles();
+        self.tick_count = 0;
+
+        debug!(
+            "spawning {} particles ({} in disabled classes)",
+            self.enabled_particle_count(),
+            self.particle_count() - self.enabled_particle_count()
+        );
 
         let mut rand = SmallRng::from_entropy();
+        let spawn_area_radius = (self.enabled_particle_count() as f32 / (self.spawn_density * PI))
+            .sqrt()
+            .max(MIN_SPAWN_AREA_RADIUS);
 
-        for c in 0..self.shared.class_count {
-            for p in 0..self.shared.particle_counts[c] {
-                self.particle_positions[(c, p)] = SPAWN_AREA_RADIUS
-                    * Vec2::angled(TAU * rand.sample::<f32, _>(Open01))
-                    * rand.sample::<f32, _>(Open01);
+        match self.spawn_shape {
+            SpawnShape::Disk => {
+                for c in 0..self.shared.class_count {
+                    for p in 0..self.shared.particle_counts[c] {
+                        self.particle_positions[(c, p)] = spawn_area_radius
+                            * Vec2::angled(TAU * rand.sample::<f32, _>(Open01))
+                            * rand.sample::<f32, _>(Open01);
+                    }
+                }
+            }
+            SpawnShape::Ring {
+                inner_radius,
+                outer_radius,
+            } => {
+                let (inner_radius, outer_radius) = (
+                    inner_radius.min(outer_radius),
+                    inner_radius.max(outer_radius),
+                );
+                for c in 0..self.shared.class_count {
+                    for p in 0..self.shared.particle_counts[c] {
+                        self.particle_positions[(c, p)] =
+                            Vec2::angled(TAU * rand.sample::<f32, _>(Open01))
+                                * (inner_radius
+                                    + (outer_radius - inner_radius)
+                                        * rand.sample::<f32, _>(Open01));
+                    }
+                }
+            }
+            SpawnShape::Grid { cols } => {
+                let cols = cols.max(1);
+                let rows = self.enabled_particle_count().div_ceil(cols);
+                // One particle per cell of area 1 / spawn_density.
+                let spacing = self.spawn_density.recip().sqrt();
+                let origin = -Vec2::new(cols as f32 - 1., rows as f32 - 1.) * spacing / 2.;
+                let mut i = 0;
+                for c in 0..self.shared.class_count {
+                    for p in 0..self.shared.particle_counts[c] {
+                        self.particle_positions[(c, p)] =
+                            origin + Vec2::new((i % cols) as f32, (i / cols) as f32) * spacing;
+                        i += 1;
+                    }
+                }
+            }
+            SpawnShape::Clustered { num_clusters } => {
+                let num_clusters = num_clusters.clamp(1, self.shared.class_count.max(1));
+                let mut cluster_counts = vec![0; num_clusters];
+                for c in 0..self.shared.class_count {
+                    cluster_counts[c % num_clusters] += self.shared.particle_counts[c];
+                }
+                for c in 0..self.shared.class_count {
+                    let cluster = c % num_clusters;
+                    let center = if num_clusters == 1 {
+                        Vec2::ZERO
+                    } else {
+                        Vec2::angled(TAU * cluster as f32 / num_clusters as f32) * spawn_area_radius
+                    };
+                    // Spread so that a cluster roughly has the spawn
+                    // density.
+                    let std_dev = (cluster_counts[cluster] as f32 / (self.spawn_density * PI))
+                        .sqrt()
+                        .max(1.)
+                        / 2.;
+                    let normal = Normal::new(0., std_dev).unwrap();
+                    for p in 0..self.shared.particle_counts[c] {
+                        self.particle_positions[(c, p)] =
+                            center + Vec2::new(normal.sample(&mut rand), normal.sample(&mut rand));
+                    }
+                }
             }
         }
+        self.perf_breakdown.spawn = start_time.elapsed();
 
-        self.sim_send
-            .send(SimResults(None, self.particle_positions.to_owned()))
-            .unwrap();
+        self.send_positions(None);
     }
 }
 
-pub fn get_partial_velocity(distance: Vec2, action_radius: f32, force: f32) -> Vec2 {
+/// Particles of the enabled classes with their position.
+pub fn enabled_particles<'a>(
+    shared: &'a SharedState,
+    particle_positions: &'a Array2D<Vec2>,
+) -> impl Iterator<Item = ((usize, usize), Vec2)> + 'a {
+    (0..shared.class_count).flat_map(move |c| {
+        (0..shared.particle_counts[c]).map(move |p| ((c, p), particle_positions[(c, p)]))
+    })
+}
+
+pub fn get_partial_velocity(
+    distance: Vec2,
+    action_radius: f32,
+    force: f32,
+    long_range_force: f32,
+    close_force: f32,
+) -> Vec2 {
     let r = distance.length();
 
     if RAMP_START_RADIUS < r && r < action_radius {
         distance.normalized() * force * ramp_then_const(r, RAMP_START_RADIUS, RAMP_LENGTH)
+    } else if action_radius <= r && r < action_radius + LONG_RANGE_LENGTH {
+        distance.normalized() * long_range_force * (action_radius + LONG_RANGE_LENGTH - r)
+            / LONG_RANGE_LENGTH
     } else if 0. < r && r <= RAMP_START_RADIUS {
-        distance.normalized() * CLOSE_FORCE * (r / RAMP_START_RADIUS - 1.)
+        distance.normalized() * close_force * (r / RAMP_START_RADIUS - 1.)
     } else {
         Vec2::ZERO
     }
@@ -246,3 +1226,81 @@ fn ramp_then_const(x: f32, zero: f32, const_start: f32) -> f32 {
     // value of const: 2. * const_start / (zero + const_start)
     (-(x - zero - const_start).abs() + x - zero + const_start) / (zero + const_start)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::channel;
+    use std::sync::{Arc, RwLock};
+
+    use super::*;
+
+    fn positions_buffer() -> PositionsBuffer {
+        Arc::new(RwLock::new(Array2D::filled_with(
+            Vec2::ZERO,
+            MAX_CLASSES,
+            MAX_PARTICLE_COUNT,
+        )))
+    }
+
+    /// Loads `value` into a new simulation, its channels are
+    /// dropped right away since nothing is sent.
+    fn load(value: Value) -> serde_json::Result<Simulation> {
+        let (_, ui_rcv) = channel();
+        let (sim_send, _) = channel();
+        Simulation::from_json(value, sim_send, ui_rcv, positions_buffer())
+    }
+
+    /// State of a simulation that was spawned and run for a few
+    /// ticks.
+    fn state_after_ticks(ticks: usize) -> Value {
+        let (_ui_send, ui_rcv) = channel();
+        // Kept open, the simulation unwraps its sends.
+        let (sim_send, _sim_rcv) = channel();
+        let mut simulation = Simulation::new(sim_send, ui_rcv, positions_buffer());
+        simulation.spawn();
+        simulation.step_n(ticks);
+        simulation.to_json()
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let json = state_after_ticks(10);
+        let simulation = load(json.to_owned()).unwrap();
+
+        assert_eq!(simulation.tick_count, 10);
+        assert_eq!(simulation.to_json(), json);
+    }
+
+    #[test]
+    fn rejects_oversized_particle_counts() {
+        let mut json = state_after_ticks(0);
+        json["shared"]["particle_counts"][0] = (MAX_PARTICLE_COUNT + 1).into();
+
+        let snapshot: SimulationSnapshot = serde_json::from_value(json.to_owned()).unwrap();
+        assert!(snapshot.validate().is_err());
+        assert!(load(json).is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_rates_masses_and_class_count() {
+        let invalid_values = [
+            ("death_rates", Value::from(-1.)),
+            ("death_rates", Value::from(MAX_POPULATION_RATE * 2.)),
+            ("birth_rates", Value::from(-1.)),
+            ("birth_rates", Value::from(MAX_POPULATION_RATE * 2.)),
+            ("class_masses", Value::from(0.)),
+            ("class_masses", Value::from(MAX_MASS * 2.)),
+        ];
+        for (key, value) in invalid_values {
+            let mut json = state_after_ticks(0);
+            json["shared"][key][0] = value;
+            assert!(load(json).is_err(), "{} accepted", key);
+        }
+
+        for class_count in [0, MIN_CLASSES - 1, MAX_CLASSES + 1] {
+            let mut json = state_after_ticks(0);
+            json["shared"]["class_count"] = class_count.into();
+            assert!(load(json).is_err(), "class count {} accepted", class_count);
+        }
+    }
+}
@@ -0,0 +1,325 @@
+use std::collections::VecDeque;
+use std::fmt::{self, Display};
+use std::fs;
+use std::num::ParseFloatError;
+use std::path::PathBuf;
+
+use array2d::Array2D;
+use directories::ProjectDirs;
+use egui::{Pos2, Vec2};
+use log::warn;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::simulation::{DAMPING_FACTOR, DT};
+use crate::ui::DEFAULT_ZOOM;
+use crate::{
+    MAX_CLASSES, MAX_PARTICLE_COUNT, RANDOM_MAX_PARTICLE_COUNT, RANDOM_MIN_PARTICLE_COUNT,
+};
+
+const HISTORY_FILE_NAME: &str = "history.json";
+const PRESETS_FILE_NAME: &str = "presets.json";
+const CONFIG_FILE_NAME: &str = "config.toml";
+const WINDOW_FILE_NAME: &str = "window.json";
+
+/// Max number of seeds kept in the history file.
+pub const MAX_SAVED_HISTORY_LEN: usize = 100;
+
+/// A named simulation configuration.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Preset {
+    pub name: String,
+    pub seed: String,
+    pub class_count: usize,
+    pub particle_counts: [usize; MAX_CLASSES],
+    pub zoom: f32,
+    pub view_pos: [f32; 2],
+    /// Custom class names, empty for presets saved before
+    /// classes could be renamed.
+    #[serde(default)]
+    pub class_names: Vec<String>,
+    /// Custom class colors (rgba), empty for presets saved
+    /// before the colors could be changed.
+    #[serde(default)]
+    pub class_colors: Vec<[u8; 4]>,
+}
+
+/// Size and position of the window in windowed mode, restored on
+/// the next launch.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct WindowGeometry {
+    /// `None` when the platform doesn't report it.
+    pub position: Option<Pos2>,
+    pub size: Vec2,
+}
+
+/// Startup defaults read from `config.toml`, missing keys keep
+/// their built-in value.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SmartConfig {
+    pub default_zoom: f32,
+    /// Fraction of its velocity a particle loses every tick.
+    pub damping_factor: f32,
+    /// Simulated time of one tick (in seconds).
+    pub dt: f32,
+    pub random_min_particle_count: usize,
+    pub random_max_particle_count: usize,
+}
+
+impl Default for SmartConfig {
+    fn default() -> Self {
+        Self {
+            default_zoom: DEFAULT_ZOOM,
+            damping_factor: DAMPING_FACTOR,
+            dt: DT,
+            random_min_particle_count: RANDOM_MIN_PARTICLE_COUNT,
+            random_max_particle_count: RANDOM_MAX_PARTICLE_COUNT,
+        }
+    }
+}
+
+impl SmartConfig {
+    /// Replaces the values that would break the simulation by
+    /// their built-in default.
+    fn validated(mut self) -> Self {
+        let default = Self::default();
+        if !self.default_zoom.is_finite() || self.default_zoom <= 0. {
+            warn!(
+                "invalid default_zoom in config, using {}",
+                default.default_zoom
+            );
+            self.default_zoom = default.default_zoom;
+        }
+        if !(0.0..=1.).contains(&self.damping_factor) {
+            warn!(
+                "invalid damping_factor in config, using {}",
+                default.damping_factor
+            );
+            self.damping_factor = default.damping_factor;
+        }
+        if !self.dt.is_finite() || self.dt <= 0. {
+            warn!("invalid dt in config, using {}", default.dt);
+            self.dt = default.dt;
+        }
+        if self.random_min_particle_count > self.random_max_particle_count
+            || self.random_max_particle_count > MAX_PARTICLE_COUNT
+        {
+            warn!(
+                "invalid random particle counts in config, using {}..{}",
+                default.random_min_particle_count, default.random_max_particle_count
+            );
+            self.random_min_particle_count = default.random_min_particle_count;
+            self.random_max_particle_count = default.random_max_particle_count;
+        }
+        self
+    }
+}
+
+/// Directory where smarticles stores its files
+/// (`~/.config/smarticles` on linux).
+fn config_dir() -> Option<PathBuf> {
+    ProjectDirs::from("", "", "smarticles").map(|dirs| dirs.config_dir().to_path_buf())
+}
+
+fn load_json<T: DeserializeOwned>(file_name: &str) -> Option<T> {
+    let path = config_dir()?.join(file_name);
+    let json = fs::read_to_string(path).ok()?;
+    match serde_json::from_str(&json) {
+        Ok(value) => Some(value),
+        Err(err) => {
+            warn!("failed to parse {}: {}", file_name, err);
+            None
+        }
+    }
+}
+
+fn save_json<T: Serialize + ?Sized>(file_name: &str, value: &T) {
+    let dir = match config_dir() {
+        Some(dir) => dir,
+        None => {
+            warn!("no config directory, {} not saved", file_name);
+            return;
+        }
+    };
+    let result = fs::create_dir_all(&dir).and_then(|_| {
+        fs::write(
+            dir.join(file_name),
+            serde_json::to_string_pretty(value).unwrap(),
+        )
+    });
+    if let Err(err) = result {
+        warn!("failed to save {}: {}", file_name, err);
+    }
+}
+
+fn remove(file_name: &str) {
+    if let Some(dir) = config_dir() {
+        let path = dir.join(file_name);
+        if path.exists() {
+            if let Err(err) = fs::remove_file(path) {
+                warn!("failed to remove {}: {}", file_name, err);
+            }
+        }
+    }
+}
+
+/// Reads the config at `path`, or at `config.toml` in the config
+/// directory when `None` (a default one is created there if it
+/// doesn't exist yet).
+pub fn load_config(path: Option<PathBuf>) -> SmartConfig {
+    let path = match path {
+        Some(path) => path,
+        None => {
+            let dir = match config_dir() {
+                Some(dir) => dir,
+                None => return SmartConfig::default(),
+            };
+            let path = dir.join(CONFIG_FILE_NAME);
+            if !path.exists() {
+                let config = SmartConfig::default();
+                let result = fs::create_dir_all(&dir)
+                    .and_then(|_| fs::write(&path, toml::to_string_pretty(&config).unwrap()));
+                if let Err(err) = result {
+                    warn!("failed to create {}: {}", CONFIG_FILE_NAME, err);
+                }
+                return config;
+            }
+            path
+        }
+    };
+
+    let text = match fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(err) => {
+            warn!("failed to read {}: {}", path.display(), err);
+            return SmartConfig::default();
+        }
+    };
+    match toml::from_str::<SmartConfig>(&text) {
+        Ok(config) => config.validated(),
+        Err(err) => {
+            warn!("failed to parse {}: {}", path.display(), err);
+            SmartConfig::default()
+        }
+    }
+}
+
+pub fn load_history() -> VecDeque<String> {
+    let mut history: VecDeque<String> = load_json(HISTORY_FILE_NAME).unwrap_or_default();
+    history.truncate(MAX_SAVED_HISTORY_LEN);
+    history
+}
+
+pub fn save_history(history: &VecDeque<String>) {
+    save_json(HISTORY_FILE_NAME, history);
+}
+
+pub fn clear_history() {
+    remove(HISTORY_FILE_NAME);
+}
+
+pub fn load_presets() -> Vec<Preset> {
+    load_json(PRESETS_FILE_NAME).unwrap_or_default()
+}
+
+pub fn save_presets(presets: &[Preset]) {
+    save_json(PRESETS_FILE_NAME, presets);
+}
+
+pub fn load_window_geometry() -> Option<WindowGeometry> {
+    load_json(WINDOW_FILE_NAME)
+}
+
+pub fn save_window_geometry(geometry: &WindowGeometry) {
+    save_json(WINDOW_FILE_NAME, geometry);
+}
+
+#[derive(Debug)]
+pub enum CsvError {
+    /// The csv doesn't have `MAX_CLASSES` rows of `MAX_CLASSES`
+    /// values.
+    WrongShape,
+    InvalidNumber(ParseFloatError),
+    /// NaN or infinite value, they would spread to every particle.
+    NotFinite(f32),
+}
+
+impl Display for CsvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CsvError::WrongShape => {
+                write!(f, "expected {} rows of {} values", MAX_CLASSES, MAX_CLASSES)
+            }
+            CsvError::InvalidNumber(err) => write!(f, "invalid number: {}", err),
+            CsvError::NotFinite(value) => write!(f, "not a finite number: {}", value),
+        }
+    }
+}
+
+/// Writes a matrix with one value per pair of classes (like the
+/// forces) as csv, one row per class.
+pub fn export_matrix_csv<T: Display>(matrix: &Array2D<T>) -> String {
+    matrix
+        .rows_iter()
+        .map(|row| {
+            row.map(|value| value.to_string())
+                .collect::<Vec<_>>()
+                .join(",")
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Parses a force matrix written by [`export_matrix_csv`].
+pub fn import_force_matrix_csv(csv: &str) -> Result<Array2D<f32>, CsvError> {
+    let rows = csv
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            line.split(',')
+                .map(|value| match value.trim().parse::<f32>() {
+                    Ok(value) if !value.is_finite() => Err(CsvError::NotFinite(value)),
+                    value => value.map_err(CsvError::InvalidNumber),
+                })
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if rows.len() != MAX_CLASSES || rows.iter().any(|row| row.len() != MAX_CLASSES) {
+        return Err(CsvError::WrongShape);
+    }
+    Array2D::from_rows(&rows).map_err(|_| CsvError::WrongShape)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn force_matrix() -> Array2D<f32> {
+        Array2D::from_iter_row_major(
+            (0..MAX_CLASSES * MAX_CLASSES).map(|i| i as f32 * 1.7 - 50.),
+            MAX_CLASSES,
+            MAX_CLASSES,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn force_matrix_csv_round_trip() {
+        let forces = force_matrix();
+        let csv = export_matrix_csv(&forces);
+        assert_eq!(import_force_matrix_csv(&csv).unwrap(), forces);
+    }
+
+    #[test]
+    fn rejects_non_finite_forces() {
+        for value in ["NaN", "inf", "-inf"] {
+            let csv = export_matrix_csv(&force_matrix()).replacen("-50", value, 1);
+            assert!(matches!(
+                import_force_matrix_csv(&csv),
+                Err(CsvError::NotFinite(_))
+            ));
+        }
+    }
+}
@@ -1,37 +1,185 @@
 use std::collections::hash_map::DefaultHasher;
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
+use std::f32::consts::{FRAC_PI_2, TAU};
+use std::fs;
 use std::hash::{Hash, Hasher};
-use std::sync::mpsc::{Receiver, Sender};
-use std::thread::JoinHandle;
+use std::mem;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
 
 use array2d::Array2D;
 use byteorder::{ReadBytesExt, WriteBytesExt, LE};
 use eframe::epaint::Color32;
 use eframe::{App, Frame};
 use egui::plot::{Line, Plot, PlotPoints};
-use egui::{CentralPanel, ComboBox, Context, ScrollArea, Sense, SidePanel, Slider, Vec2};
+use egui::{
+    color_picker, pos2, Align, Align2, Area, Button, CentralPanel, CollapsingHeader, ComboBox,
+    Context, DragValue, FontId, Grid, Key, Painter, Pos2, ProgressBar, Rect, Response, ScrollArea,
+    Sense, Shape, SidePanel, Slider, Stroke, TextEdit, Ui, Vec2, Window,
+};
 use rand::distributions::Open01;
 use rand::rngs::SmallRng;
 use rand::{Rng, SeedableRng};
 use rayon::prelude::*;
+use rfd::FileDialog;
+use serde_json::Value;
 
-use crate::simulation::{get_partial_velocity, SimulationState};
+use crate::basin::{BasinBuffer, MAX_BASIN_RUNS, MIN_BASIN_RUNS};
+use crate::batch::{self, BatchMetric, BatchResult, BatchSeedRunner};
+use crate::export::export_as_svg;
+use crate::grid::CellGrid;
+use crate::recording::Recording;
+use crate::simulation::{
+    enabled_particles, get_partial_velocity, BoundaryShape, SimulationSnapshot, SimulationState,
+    SpawnShape, ADJACENCY_RANGE, LONG_RANGE_LENGTH, PAUSED_UPDATE_INTERVAL, UPDATE_INTERVAL,
+};
+use crate::storage::{self, Preset, WindowGeometry, MAX_SAVED_HISTORY_LEN};
 use crate::{
-    SharedState, SimResults, UiEvent, UpdateSharedState, FORCE_FACTOR, MAX_CLASSES, MAX_FORCE,
-    MAX_PARTICLE_COUNT, MAX_RADIUS, MIN_CLASSES, MIN_FORCE, MIN_PARTICLE_COUNT, MIN_RADIUS,
-    RANDOM_MAX_PARTICLE_COUNT, RANDOM_MIN_PARTICLE_COUNT,
+    class_limit, config, copy_particle_positions, epoch_millis, spawn_simulation, AttractorMode,
+    Param, PerfBreakdown, PositionsBuffer, SharedState, SimResults, SimulationThread, UiEvent,
+    UpdateSharedState, DEFAULT_CLOSE_FORCE, DEFAULT_SPAWN_DENSITY, FORCE_FACTOR,
+    MAX_ATTRACTOR_STRENGTH, MAX_CLASSES, MAX_CLOSE_FORCE, MAX_FORCE, MAX_GRAVITY,
+    MAX_LONG_RANGE_FORCE, MAX_MASS, MAX_PARTICLE_COUNT, MAX_POPULATION_RATE, MAX_RADIUS,
+    MAX_SPAWN_DENSITY, MAX_TEMPERATURE, MAX_THREAD_COUNT, MAX_VORTEX_STRENGTH, MIN_CLASSES,
+    MIN_CLOSE_FORCE, MIN_FORCE, MIN_LONG_RANGE_FORCE, MIN_MASS, MIN_PARTICLE_COUNT, MIN_RADIUS,
+    MIN_SPAWN_DENSITY,
 };
 
 /// Display diameter of the particles in the simulation (in
 /// pixels).
-const PARTICLE_DIAMETER: f32 = 1.;
+pub const PARTICLE_DIAMETER: f32 = 1.;
 
-const DEFAULT_ZOOM: f32 = 2.;
+/// Built-in default of
+/// [`SmartConfig::default_zoom`](crate::storage::SmartConfig::default_zoom).
+pub const DEFAULT_ZOOM: f32 = 2.;
 const MIN_ZOOM: f32 = 0.1;
 const MAX_ZOOM: f32 = 30.;
 const ZOOM_FACTOR: f32 = 1.08;
 
+const DEFAULT_BATCH_SEED_COUNT: usize = 10;
+const MAX_BATCH_SEED_COUNT: usize = 100;
+const DEFAULT_BATCH_TICK_COUNT: usize = 1000;
+const MIN_BATCH_TICK_COUNT: usize = 100;
+const MAX_BATCH_TICK_COUNT: usize = 10000;
+
+/// Max height of the list of particles near the selected one.
+const NEARBY_PARTICLES_HEIGHT: f32 = 200.;
+
+/// Number of ticks the particles are moved ahead by the step
+/// button.
+const STEP_TICK_COUNT: usize = 100;
+
+/// Number of history entries shown in the seed history (the
+/// history file keeps more of them, see
+/// [`MAX_SAVED_HISTORY_LEN`]).
 const MAX_HISTORY_LEN: usize = 10;
+/// Size of the force previews of the seed history (in pixels).
+const SEED_PREVIEW_SIZE: f32 = 16.;
+
+const DEFAULT_MUTATION_NOISE: i8 = 5;
+const MAX_MUTATION_NOISE: i8 = 50;
+
+/// How much the opacity of a class changes each frame when it
+/// is being enabled or disabled.
+const FADE_RATE: f32 = 0.05;
+
+/// Number of force field samples along each axis of the canvas.
+const FORCE_FIELD_RESOLUTION: usize = 20;
+
+/// Size of the interaction graph (in pixels).
+const INTERACTION_GRAPH_SIZE: f32 = 200.;
+/// Radius of the class nodes in the interaction graph (in
+/// pixels).
+const INTERACTION_GRAPH_NODE_RADIUS: f32 = 8.;
+/// Max distance between the pointer and an edge of the
+/// interaction graph for a click to select it (in pixels).
+const INTERACTION_GRAPH_CLICK_DISTANCE: f32 = 5.;
+
+/// Max number of connection lines drawn per frame.
+const MAX_CONNECTION_LINES: usize = 20000;
+/// Opacity of the connection line between two touching
+/// particles.
+const CONNECTION_LINE_OPACITY: f32 = 0.5;
+
+const DEFAULT_RING_INNER_RADIUS: f32 = 50.;
+const DEFAULT_RING_OUTER_RADIUS: f32 = 100.;
+const MAX_RING_RADIUS: f32 = 1000.;
+const DEFAULT_GRID_COLS: usize = 32;
+const MAX_GRID_COLS: usize = 256;
+
+const DEFAULT_BOUNDARY_SIZE: f32 = 400.;
+const MIN_BOUNDARY_SIZE: f32 = 50.;
+const MAX_BOUNDARY_SIZE: f32 = 2000.;
+const DEFAULT_RESTITUTION: f32 = 0.5;
+const DEFAULT_MAX_PARTICLES_PER_CELL: usize = 50;
+const MAX_PARTICLES_PER_CELL: usize = 500;
+/// Number of segments used to draw the ellipse boundary.
+const ELLIPSE_SEGMENTS: usize = 64;
+
+/// Size of the minimap (in pixels).
+const MINIMAP_SIZE: f32 = 150.;
+
+/// Length of the scale ruler (in pixels).
+const RULER_LENGTH: f32 = 100.;
+/// Distance between the canvas overlays and the canvas borders
+/// (in pixels).
+const OVERLAY_MARGIN: f32 = 10.;
+
+/// Height of the bars of the particle count chart (in pixels).
+const PARTICLE_COUNT_BAR_HEIGHT: f32 = 12.;
+/// Height of the stacked bar of the performance breakdown.
+const PERF_BAR_HEIGHT: f32 = 12.;
+
+/// Number of calculation times used to compute the performance
+/// stats.
+const CALCULATION_TIME_HISTORY_LEN: usize = 200;
+
+/// Duration of the transition of the force matrix heatmap when
+/// the forces change.
+const FORCE_ANIMATION_DURATION: Duration = Duration::from_millis(500);
+
+/// Number of order parameters kept to show the trend.
+const ORDER_PARAMETER_HISTORY_LEN: usize = 200;
+const DEFAULT_ORDER_THRESHOLD: f32 = 0.8;
+
+/// Tick at which the pattern score is computed, once the
+/// particles had time to organize.
+const PATTERN_SCORE_TICK: u64 = 500;
+/// Number of cells on each side of the grid used to compute the
+/// pattern score.
+const PATTERN_GRID_SIZE: usize = 32;
+const DEFAULT_PATTERN_THRESHOLD: f32 = 0.7;
+
+/// Number of frames used to compute the FPS.
+const FPS_SAMPLE_COUNT: usize = 60;
+const DEFAULT_TARGET_FPS: f32 = 60.;
+const MIN_TARGET_FPS: f32 = 10.;
+const MAX_TARGET_FPS: f32 = 240.;
+
+/// Time between two checks of the simulation thread heartbeat.
+const WATCHDOG_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How long notifications stay on screen.
+const TOAST_DURATION: Duration = Duration::from_secs(2);
+
+/// First byte of the custom seeds that store the params as
+/// `f32` (older seeds start with the class count, which is never
+/// this high).
+const SEED_FORMAT_F32: u8 = 0xff;
+
+/// Max number of undo steps kept.
+const MAX_UNDO_LEN: usize = 50;
+
+/// In the comparison diff highlight, a particle is highlighted
+/// when it is farther than this many times the mean distance of
+/// the class to its centroid in the other simulation.
+const DIFF_SPREAD_FACTOR: f32 = 2.;
+const DIFF_HIGHLIGHT_COLOR: Color32 = Color32::WHITE;
+
+const NO_INTERACTION_COLOR: Color32 = Color32::from_gray(120);
 
 pub struct View {
     zoom: f32,
@@ -42,13 +190,31 @@ pub struct View {
 }
 
 impl View {
-    const DEFAULT: View = Self {
-        zoom: DEFAULT_ZOOM,
-        pos: Vec2::ZERO,
-        dragging: false,
-        drag_start_pos: Vec2::ZERO,
-        drag_start_view_pos: Vec2::ZERO,
-    };
+    fn new() -> Self {
+        Self {
+            zoom: config().default_zoom,
+            pos: Vec2::ZERO,
+            dragging: false,
+            drag_start_pos: Vec2::ZERO,
+            drag_start_view_pos: Vec2::ZERO,
+        }
+    }
+}
+
+/// What the view is centered on.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FollowMode {
+    None,
+    Particle(usize, usize),
+    ClassCentroid(usize),
+    GlobalCentroid,
+}
+
+/// State restored by one undo/redo step.
+#[derive(Debug, Clone)]
+struct UndoEntry {
+    param_matrix: Array2D<Param>,
+    particle_counts: [usize; MAX_CLASSES],
 }
 
 #[derive(Debug)]
@@ -58,29 +224,274 @@ struct ClassProps {
     color: Color32,
 }
 
+/// Forces shown by the force matrix heatmap, moving from the
+/// previous forces to the current ones.
+struct ForceAnimation {
+    from: Array2D<f32>,
+    to: Array2D<f32>,
+    start: Instant,
+}
+
+impl ForceAnimation {
+    fn new(forces: Array2D<f32>) -> Self {
+        Self {
+            from: forces.to_owned(),
+            to: forces,
+            start: Instant::now(),
+        }
+    }
+
+    /// Progress of the transition, between 0 and 1.
+    fn progress(&self) -> f32 {
+        (self.start.elapsed().as_secs_f32() / FORCE_ANIMATION_DURATION.as_secs_f32()).min(1.)
+    }
+
+    fn force(&self, i: usize, j: usize) -> f32 {
+        let t = self.progress();
+        self.from[(i, j)] * (1. - t) + self.to[(i, j)] * t
+    }
+
+    /// Starts a transition to `forces` from the forces currently
+    /// shown, if they changed.
+    fn update(&mut self, forces: Array2D<f32>) {
+        if forces == self.to {
+            return;
+        }
+        self.from = Array2D::from_iter_row_major(
+            (0..MAX_CLASSES)
+                .flat_map(|i| (0..MAX_CLASSES).map(move |j| (i, j)))
+                .map(|(i, j)| self.force(i, j)),
+            MAX_CLASSES,
+            MAX_CLASSES,
+        )
+        .unwrap();
+        self.to = forces;
+        self.start = Instant::now();
+    }
+}
+
+/// What the seed history shows of a seed.
+struct SeedPreview {
+    class_count: usize,
+    forces: Array2D<f32>,
+}
+
+/// Second simulation running another seed, shown next to the
+/// main one.
+struct Comparison {
+    seed: String,
+    shared: SharedState,
+    particle_positions: Array2D<Vec2>,
+
+    ui_send: Sender<UiEvent>,
+    sim_rcv: Receiver<SimResults>,
+    positions_buffer: PositionsBuffer,
+}
+
 pub struct Smarticles {
     shared: SharedState,
 
     classes: [ClassProps; MAX_CLASSES],
+    /// Contents of the class name text fields, applied when
+    /// they lose focus.
+    class_name_edits: [String; MAX_CLASSES],
     particle_positions: Array2D<Vec2>,
+    /// Opacity of each class, goes to 0 when the class is
+    /// disabled and back to 1 when it is enabled.
+    disabled_opacity: [f32; MAX_CLASSES],
+    /// Whether a class count decrease is waiting for the
+    /// disabled classes to fade out before being sent to the
+    /// simulation.
+    class_count_pending: bool,
 
     seed: String,
+    /// Max force change applied to each param when mutating the
+    /// seed.
+    mutation_noise: i8,
+    seed_a: String,
+    seed_b: String,
+    blend_factor: f32,
+    crossover_probability: f32,
 
     view: View,
+    /// Size of the canvas during the last frame, used when
+    /// exporting it.
+    canvas_size: Vec2,
 
     selected_param: (usize, usize),
     selected_particle: (usize, usize),
-    follow_selected_particle: bool,
+    follow_mode: FollowMode,
+
+    show_force_field: bool,
+    force_field_class: usize,
+
+    show_minimap: bool,
+    /// Draw lines between the particles that interact with each
+    /// other.
+    show_connections: bool,
+    /// Kept between the frames, see
+    /// [`Smarticles::draw_connections`].
+    connection_grid: CellGrid,
+    /// Color the particles by the sign of the force of the class
+    /// they are surrounded by, see
+    /// [`Smarticles::interaction_colors`].
+    color_by_interaction: bool,
+    /// Kept between the frames, see
+    /// [`Smarticles::interaction_colors`].
+    interaction_grid: CellGrid,
+
+    temperature: f32,
+    /// Direction of the gravity (in degrees, 90 is down).
+    gravity_angle: f32,
+    gravity_magnitude: f32,
+    attractor: AttractorMode,
+    /// Whether the attractor was set from the canvas menu, it then
+    /// stays in place instead of following the mouse.
+    attractor_pinned: bool,
+    vortex_strength: f32,
+    boundary: BoundaryShape,
+    restitution: f32,
+    max_particles_per_cell: Option<usize>,
+    spawn_density: f32,
+    spawn_shape: SpawnShape,
+    close_force: f32,
+    /// See [`UiEvent::FreezeClass`].
+    frozen_classes: [bool; MAX_CLASSES],
+
+    /// Positions of the extra runs of the basin explorer, `None`
+    /// when it is disabled.
+    basin_buffer: Option<BasinBuffer>,
+    /// Number of runs shown by the basin explorer (including the
+    /// main one).
+    basin_runs: usize,
+
+    /// When enabled, the force of class i on class j is always
+    /// the same as the force of class j on class i.
+    symmetric_mode: bool,
+
+    show_interaction_graph: bool,
+    /// Position (in simulation space) where the canvas menu was
+    /// opened.
+    context_menu_pos: Vec2,
+    /// See [`SimResults::interaction_counts`].
+    interaction_counts: Array2D<usize>,
+    /// See [`SimResults::adjacency_counts`].
+    adjacency_counts: Array2D<usize>,
+    /// Param whose slider should be scrolled to in the sidebar.
+    scroll_to_param: Option<(usize, usize)>,
 
     history: VecDeque<String>,
     selected_history_entry: usize,
+    /// Previews of the seeds of the history, computed when they
+    /// are first shown.
+    history_previews: HashMap<String, SeedPreview>,
+
+    undo_stack: Vec<UndoEntry>,
+    redo_stack: Vec<UndoEntry>,
+
+    /// Forces the current ones are compared to when the matrix
+    /// delta is shown.
+    force_baseline: Option<Array2D<f32>>,
+    force_animation: ForceAnimation,
+
+    comparison: Option<Comparison>,
+    /// Seed of the next comparison, a random one is picked when
+    /// empty.
+    comparison_seed: String,
+    /// Highlight the particles that are not where their class is
+    /// in the other simulation.
+    diff_highlight: bool,
+
+    /// Positions recorded every tick, see [`Recording`].
+    recording: Option<Recording>,
+    /// Whether new ticks are added to the recording.
+    is_recording: bool,
+    /// Frame of the recording shown instead of the simulation.
+    playback_frame: Option<usize>,
+    playback_playing: bool,
+    last_playback_step: Instant,
+
+    presets: Vec<Preset>,
+    selected_preset: usize,
+    preset_name: String,
+
+    /// Whether the next pasted text should be applied as the
+    /// seed.
+    awaiting_paste: bool,
+    toast: Option<(String, Instant)>,
 
     calculation_time: u128,
+    tick_count: u64,
+    last_frame_time: Option<Instant>,
+    /// Duration of the last frames (in seconds), used to compute
+    /// the FPS.
+    frame_durations: VecDeque<f32>,
+    /// When set, frames are requested at this rate instead of as
+    /// fast as possible.
+    target_fps: Option<f32>,
+    /// Whether smarticles was started with `--windowed`, the
+    /// window geometry is only saved then.
+    windowed: bool,
+    /// Last known size and position of the window.
+    window_geometry: Option<WindowGeometry>,
+    /// Number of threads used by the simulation.
+    thread_count: usize,
+
+    order_parameter: f32,
+    /// Last order parameters, used to show the trend.
+    order_parameter_history: VecDeque<f32>,
+    /// Whether the seed is added to the history when the order
+    /// parameter goes above `order_threshold`.
+    auto_save_ordered: bool,
+    order_threshold: f32,
+
+    /// See [`pattern_score`], `None` until [`PATTERN_SCORE_TICK`]
+    /// is reached.
+    pattern_score: Option<f32>,
+    /// Seeds whose pattern score is below it are flagged.
+    pattern_threshold: f32,
+    /// Seeds that produced structured patterns, with their score.
+    flagged_seeds: Vec<(String, f32)>,
+
+    /// Number of random seeds run by the seed explorer.
+    batch_seed_count: usize,
+    /// Number of ticks each seed is run for before being scored.
+    batch_tick_count: usize,
+    batch_metric: BatchMetric,
+    /// Receives the ranked seeds when the exploration running in
+    /// the background is done.
+    batch_rcv: Option<Receiver<Vec<BatchResult>>>,
+    /// Stops the exploration running in the background.
+    batch_stop: Arc<AtomicBool>,
+    batch_results: Vec<BatchResult>,
+
+    /// Receives the outcome of the last [`UiEvent::SaveState`].
+    save_rcv: Option<Receiver<Result<(), String>>>,
+
+    /// Whether the kinetic energy is shown, the simulation only
+    /// sends the velocities when it is.
+    show_kinetic_energy: bool,
+    /// Whether the adjacent pairs are counted, the simulation only
+    /// counts them when they are shown or exported.
+    count_adjacencies: bool,
+    /// Total kinetic energy of the enabled particles, `None` until
+    /// velocities are received.
+    kinetic_energy: Option<f32>,
+
+    perf_breakdown: PerfBreakdown,
+    /// Last calculation times, used for the performance stats.
+    calculation_time_history: VecDeque<Duration>,
+    /// Highest calculation time since the last reset.
+    peak_calculation_time: Duration,
 
     words: Vec<String>,
 
     ui_send: Sender<UiEvent>,
     sim_rcv: Receiver<SimResults>,
+    positions_buffer: PositionsBuffer,
+    heartbeat: Arc<AtomicU64>,
+    last_watchdog_check: Instant,
+    simulation_unresponsive: bool,
 
     simulation_handle: Option<JoinHandle<()>>,
 }
@@ -88,9 +499,8 @@ pub struct Smarticles {
 impl Smarticles {
     pub fn new<S>(
         classes: [(S, Color32); MAX_CLASSES],
-        ui_send: Sender<UiEvent>,
-        sim_rcv: Receiver<SimResults>,
-        simulation_handle: Option<JoinHandle<()>>,
+        simulation: SimulationThread,
+        windowed: bool,
     ) -> Self
     where
         S: ToString,
@@ -111,39 +521,160 @@ impl Smarticles {
             })
             .collect();
 
+        let class_name_edits = classes.each_ref().map(|(name, _)| name.to_string());
+
         Self {
             shared: SharedState::new(),
 
             seed: "".to_string(),
+            mutation_noise: DEFAULT_MUTATION_NOISE,
+            seed_a: "".to_string(),
+            seed_b: "".to_string(),
+            blend_factor: 0.5,
+            crossover_probability: 0.5,
 
             classes: classes.map(|(name, color)| ClassProps {
                 name: name.to_string(),
                 heading: "class ".to_string() + &name.to_string(),
                 color,
             }),
+            class_name_edits,
             particle_positions: Array2D::filled_with(Vec2::ZERO, MAX_CLASSES, MAX_PARTICLE_COUNT),
+            disabled_opacity: [1.; MAX_CLASSES],
+            class_count_pending: false,
 
-            view: View::DEFAULT,
+            view: View::new(),
+            canvas_size: Vec2::ZERO,
 
             selected_param: (0, 0),
             selected_particle: (0, 0),
-            follow_selected_particle: false,
+            follow_mode: FollowMode::None,
+
+            show_force_field: false,
+            force_field_class: 0,
+
+            show_minimap: false,
+            show_connections: false,
+            connection_grid: CellGrid::new(MAX_RADIUS),
+            color_by_interaction: false,
+            interaction_grid: CellGrid::new(ADJACENCY_RANGE),
+
+            temperature: 0.,
+            gravity_angle: 90.,
+            gravity_magnitude: 0.,
+            attractor: AttractorMode::INACTIVE,
+            attractor_pinned: false,
+            vortex_strength: 0.,
+            boundary: BoundaryShape::None,
+            restitution: DEFAULT_RESTITUTION,
+            max_particles_per_cell: None,
+            spawn_density: DEFAULT_SPAWN_DENSITY,
+            close_force: DEFAULT_CLOSE_FORCE,
+            spawn_shape: SpawnShape::Disk,
+            frozen_classes: [false; MAX_CLASSES],
 
-            history: VecDeque::new(),
+            basin_buffer: None,
+            basin_runs: MIN_BASIN_RUNS,
+
+            symmetric_mode: false,
+
+            show_interaction_graph: false,
+            context_menu_pos: Vec2::ZERO,
+            interaction_counts: Array2D::filled_with(0, MAX_CLASSES, MAX_CLASSES),
+            adjacency_counts: Array2D::filled_with(0, MAX_CLASSES, MAX_CLASSES),
+            scroll_to_param: None,
+
+            history: storage::load_history(),
             selected_history_entry: 0,
+            history_previews: HashMap::new(),
+
+            undo_stack: Vec::new(),
+            redo_stack: Vec::new(),
+
+            force_baseline: None,
+            force_animation: ForceAnimation::new(Array2D::filled_with(
+                0.,
+                MAX_CLASSES,
+                MAX_CLASSES,
+            )),
+
+            comparison: None,
+            comparison_seed: "".to_string(),
+            diff_highlight: false,
+
+            recording: None,
+            is_recording: false,
+            playback_frame: None,
+            playback_playing: false,
+            last_playback_step: Instant::now(),
+
+            presets: storage::load_presets(),
+            selected_preset: 0,
+            preset_name: "".to_string(),
+
+            awaiting_paste: false,
+            toast: None,
 
             calculation_time: 0,
+            tick_count: 0,
+            last_frame_time: None,
+            frame_durations: VecDeque::new(),
+            target_fps: None,
+            windowed,
+            window_geometry: None,
+            thread_count: rayon::current_num_threads(),
+
+            order_parameter: 0.,
+            order_parameter_history: VecDeque::new(),
+            auto_save_ordered: false,
+            order_threshold: DEFAULT_ORDER_THRESHOLD,
+
+            pattern_score: None,
+            pattern_threshold: DEFAULT_PATTERN_THRESHOLD,
+            flagged_seeds: Vec::new(),
+
+            batch_seed_count: DEFAULT_BATCH_SEED_COUNT,
+            batch_tick_count: DEFAULT_BATCH_TICK_COUNT,
+            batch_metric: BatchMetric::OrderParameter,
+            batch_rcv: None,
+            batch_stop: Arc::new(AtomicBool::new(false)),
+            batch_results: Vec::new(),
+
+            save_rcv: None,
+
+            show_kinetic_energy: false,
+            count_adjacencies: false,
+            kinetic_energy: None,
+
+            perf_breakdown: PerfBreakdown::default(),
+            calculation_time_history: VecDeque::new(),
+            peak_calculation_time: Duration::ZERO,
 
             words,
 
-            ui_send,
-            sim_rcv,
+            ui_send: simulation.ui_send,
+            sim_rcv: simulation.sim_rcv,
+            positions_buffer: simulation.positions_buffer,
+            heartbeat: simulation.heartbeat,
+            last_watchdog_check: Instant::now(),
+            simulation_unresponsive: false,
 
-            simulation_handle,
+            simulation_handle: Some(simulation.handle),
         }
     }
 
     fn apply_seed(&mut self) {
+        self.load_seed();
+        self.check_symmetric_mode();
+
+        self.send_params();
+        self.send_class_count();
+        self.send_particle_counts();
+    }
+
+    /// Sets the params and particle counts from the seed without
+    /// sending them to the simulation.
+    fn load_seed(&mut self) {
         let mut rand = if self.seed.is_empty() {
             SmallRng::from_entropy()
         } else {
@@ -164,20 +695,96 @@ impl Smarticles {
 
         for i in 0..self.shared.class_count {
             self.shared.particle_counts[i] = rand(
-                RANDOM_MIN_PARTICLE_COUNT as f32,
-                RANDOM_MAX_PARTICLE_COUNT as f32,
+                config().random_min_particle_count as f32,
+                config().random_max_particle_count as f32,
             ) as usize;
             for j in 0..self.shared.class_count {
                 let pow = rand(MIN_FORCE, MAX_FORCE);
                 self.shared.param_matrix[(i, j)].force = pow.signum() * pow.abs().powf(1. / POW_F);
                 self.shared.param_matrix[(i, j)].radius =
                     rand(MIN_RADIUS, MAX_RADIUS).powf(1. / RAD_F);
+                // Word seeds predate long-range forces.
+                self.shared.param_matrix[(i, j)].long_range_force = 0.;
+            }
+        }
+    }
+
+    /// Params and particle counts given by `seed`, it is turned
+    /// into params the same way as the current seed, then the
+    /// current state is put back.
+    fn decode_seed(&mut self, seed: &str) -> SharedState {
+        let current_seed = mem::replace(&mut self.seed, seed.to_owned());
+        let shared = self.shared.to_owned();
+        self.load_seed();
+        let decoded = mem::replace(&mut self.shared, shared);
+        self.seed = current_seed;
+        decoded
+    }
+
+    /// Computes the previews of the seeds of the history that
+    /// are shown.
+    fn cache_history_previews(&mut self) {
+        let missing = self
+            .history
+            .iter()
+            .take(MAX_HISTORY_LEN)
+            .filter(|seed| !self.history_previews.contains_key(*seed))
+            .cloned()
+            .collect::<Vec<_>>();
+        for seed in missing {
+            let shared = self.decode_seed(&seed);
+            self.history_previews.insert(
+                seed,
+                SeedPreview {
+                    class_count: shared.class_count,
+                    forces: Array2D::from_iter_row_major(
+                        shared
+                            .param_matrix
+                            .elements_row_major_iter()
+                            .map(|p| p.force),
+                        MAX_CLASSES,
+                        MAX_CLASSES,
+                    )
+                    .unwrap(),
+                },
+            );
+        }
+    }
+
+    /// Slightly changes the forces of the current seed and
+    /// applies the resulting custom seed.
+    fn mutate_seed(&mut self) {
+        // Makes sure the params are the ones of the current seed
+        // (word seeds are turned into their params this way).
+        self.apply_seed();
+
+        let mut rand = SmallRng::from_entropy();
+        for i in 0..self.shared.class_count {
+            for j in 0..self.shared.class_count {
+                if self.symmetric_mode && j < i {
+                    self.shared.param_matrix[(i, j)].force = self.shared.param_matrix[(j, i)].force;
+                    continue;
+                }
+                let noise = rand.gen_range(-self.mutation_noise..=self.mutation_noise);
+                let force = &mut self.shared.param_matrix[(i, j)].force;
+                *force = (*force + noise as f32).clamp(MIN_FORCE, MAX_FORCE);
             }
         }
 
+        self.seed = self.export();
+        self.update_history();
+
         self.send_params();
-        self.send_class_count();
-        self.send_particle_counts();
+        self.spawn();
+    }
+
+    /// Applies the given custom seed bytes as the new seed.
+    fn apply_seed_bytes(&mut self, bytes: Vec<u8>) {
+        self.seed = format!("@{}", base64::encode(bytes));
+        self.update_history();
+
+        self.apply_seed();
+        self.spawn();
     }
 
     fn send_params(&self) {
@@ -197,12 +804,120 @@ impl Smarticles {
             ))
             .unwrap();
     }
+    fn send_class_masses(&self) {
+        self.ui_send
+            .send(UiEvent::ClassMassesUpdate(self.shared.class_masses))
+            .unwrap();
+    }
+
+    fn send_population_rates(&self) {
+        self.ui_send
+            .send(UiEvent::PopulationRatesUpdate(
+                self.shared.birth_rates,
+                self.shared.death_rates,
+            ))
+            .unwrap();
+    }
+
+    /// Whether particles can be born or die, in which case the
+    /// simulation decides the particle counts.
+    fn population_changes(&self) -> bool {
+        self.shared.birth_rates.iter().any(|r| *r > 0.)
+            || self.shared.death_rates.iter().any(|r| *r > 0.)
+    }
+
+    fn gravity(&self) -> Vec2 {
+        Vec2::angled(self.gravity_angle.to_radians()) * self.gravity_magnitude
+    }
+
+    fn send_thread_count(&self) {
+        self.ui_send
+            .send(UiEvent::SetThreadCount(self.thread_count))
+            .unwrap();
+    }
+
+    fn send_temperature(&self) {
+        self.ui_send
+            .send(UiEvent::SetTemperature(self.temperature))
+            .unwrap();
+    }
+
+    fn send_gravity(&self) {
+        self.ui_send
+            .send(UiEvent::SetGravity(self.gravity()))
+            .unwrap();
+    }
+
+    fn send_vortex_strength(&self) {
+        self.ui_send
+            .send(UiEvent::SetVortexStrength(self.vortex_strength))
+            .unwrap();
+    }
+
+    fn send_boundary(&self) {
+        self.ui_send
+            .send(UiEvent::SetBoundary(self.boundary, self.restitution))
+            .unwrap();
+    }
+
+    fn send_spawn_density(&self) {
+        self.ui_send
+            .send(UiEvent::SetSpawnDensity(self.spawn_density))
+            .unwrap();
+    }
+
+    fn send_close_force(&self) {
+        self.ui_send
+            .send(UiEvent::SetCloseForce(self.close_force))
+            .unwrap();
+    }
+
+    fn send_spawn_shape(&self) {
+        self.ui_send
+            .send(UiEvent::SetSpawnShape(self.spawn_shape))
+            .unwrap();
+    }
+
+    fn send_frozen_class(&self, c: usize) {
+        self.ui_send
+            .send(if self.frozen_classes[c] {
+                UiEvent::FreezeClass(c)
+            } else {
+                UiEvent::UnfreezeClass(c)
+            })
+            .unwrap();
+    }
+
+    fn send_max_particles_per_cell(&self) {
+        self.ui_send
+            .send(UiEvent::SetMaxParticlesPerCell(self.max_particles_per_cell))
+            .unwrap();
+    }
+
+    /// Starts the basin explorer with a new buffer, or stops it if
+    /// `enabled` is `false`.
+    fn send_basin_explorer(&mut self, enabled: bool) {
+        if enabled {
+            let buffer = BasinBuffer::default();
+            self.ui_send
+                .send(UiEvent::StartBasinExplorer(
+                    self.basin_runs - 1,
+                    buffer.clone(),
+                ))
+                .unwrap();
+            self.basin_buffer = Some(buffer);
+        } else {
+            self.ui_send.send(UiEvent::StopBasinExplorer).unwrap();
+            self.basin_buffer = None;
+        }
+    }
 
     fn export(&self) -> String {
         let mut bytes: Vec<u8> = Vec::new();
         // bytes
         //     .write_u16::<LE>(self.shared.world_radius as u16)
         //     .unwrap();
+        bytes.write_u8(SEED_FORMAT_F32).unwrap();
         bytes.write_u8(self.shared.class_count as u8).unwrap();
         for count in &self.shared.particle_counts {
             bytes.write_u16::<LE>(*count as u16).unwrap();
@@ -211,239 +926,2652 @@ impl Smarticles {
             .param_matrix
             .elements_row_major_iter()
             .for_each(|p| {
-                bytes.write_i8(p.force as i8).unwrap();
-                bytes.write_i8(p.radius as i8).unwrap();
+                bytes.write_f32::<LE>(p.force).unwrap();
+                bytes.write_f32::<LE>(p.radius).unwrap();
             });
+        // Appended so that seeds without them still decode.
+        self.shared
+            .param_matrix
+            .elements_row_major_iter()
+            .for_each(|p| bytes.write_f32::<LE>(p.long_range_force).unwrap());
 
         format!("@{}", base64::encode(bytes))
     }
 
     fn import(&mut self, mut bytes: &[u8]) {
+        let f32_params = read_seed_format(&mut bytes);
         // self.shared.world_radius = bytes
         //     .read_u16::<LE>()
         //     .unwrap_or(DEFAULT_WORLD_RADIUS as u16) as f32;
-        self.shared.class_count = bytes.read_u8().unwrap_or(MAX_CLASSES as u8) as usize;
+        self.shared.class_count =
+            (bytes.read_u8().unwrap_or(MAX_CLASSES as u8) as usize).min(class_limit());
         for count in &mut self.shared.particle_counts {
             // let r = (bytes.read_u8().unwrap_or((p.color.r() * 255.) as u8) as f32) / 255.;
             // let g = (bytes.read_u8().unwrap_or((p.color.g() * 255.) as u8) as f32) / 255.;
             // let b = (bytes.read_u8().unwrap_or((p.color.b() * 255.) as u8) as f32) / 255.;
             // p.color = Rgba::from_rgb(r, g, b);
-            *count = bytes.read_u16::<LE>().unwrap_or(0) as usize;
+            *count = (bytes.read_u16::<LE>().unwrap_or(0) as usize).min(MAX_PARTICLE_COUNT);
         }
 
         for i in 0..MAX_CLASSES {
             for j in 0..MAX_CLASSES {
-                self.shared.param_matrix[(i, j)].force = bytes.read_i8().unwrap_or(0) as f32;
-                self.shared.param_matrix[(i, j)].radius = bytes.read_i8().unwrap_or(0) as f32;
+                self.shared.param_matrix[(i, j)].force = read_seed_param(&mut bytes, f32_params);
+                self.shared.param_matrix[(i, j)].radius = read_seed_param(&mut bytes, f32_params);
+            }
+        }
+        for i in 0..MAX_CLASSES {
+            for j in 0..MAX_CLASSES {
+                self.shared.param_matrix[(i, j)].long_range_force =
+                    bytes.read_f32::<LE>().unwrap_or(0.);
             }
         }
     }
 
-    fn update_history(&mut self) {
-        self.history.push_front(self.seed.to_owned());
-        if self.history.len() > MAX_HISTORY_LEN {
-            self.history.pop_back();
+    /// Geometric center of the given class, or `None` if the
+    /// class is disabled or has no particles.
+    fn class_centroid(&self, c: usize) -> Option<Vec2> {
+        let count = self.shared.particle_counts[c];
+        if c >= self.shared.class_count || count == 0 {
+            return None;
         }
-        self.selected_history_entry = 0;
+        let sum = (0..count).fold(Vec2::ZERO, |sum, p| sum + self.particle_positions[(c, p)]);
+        Some(sum / count as f32)
     }
-}
 
-impl UpdateSharedState for Smarticles {
-    fn play(&mut self) {
-        self.shared.simulation_state = SimulationState::Running;
-        self.ui_send.send(UiEvent::Play).unwrap();
+    /// Geometric center of all the particles of the enabled
+    /// classes.
+    fn global_centroid(&self) -> Option<Vec2> {
+        let mut sum = Vec2::ZERO;
+        let mut count = 0;
+        for c in 0..self.shared.class_count {
+            for p in 0..self.shared.particle_counts[c] {
+                sum += self.particle_positions[(c, p)];
+            }
+            count += self.shared.particle_counts[c];
+        }
+        if count > 0 {
+            Some(sum / count as f32)
+        } else {
+            None
+        }
     }
-    fn pause(&mut self) {
-        self.shared.simulation_state = SimulationState::Paused;
-        self.ui_send.send(UiEvent::Pause).unwrap();
+
+    /// Position the view is currently centered on, depending on
+    /// the follow mode.
+    fn follow_target(&self) -> Option<Vec2> {
+        match self.follow_mode {
+            FollowMode::None => None,
+            FollowMode::Particle(c, p) => Some(self.particle_positions[(c, p)]),
+            FollowMode::ClassCentroid(c) => self.class_centroid(c),
+            FollowMode::GlobalCentroid => self.global_centroid(),
+        }
     }
-    fn reset(&mut self) {
-        self.shared.simulation_state = SimulationState::Stopped;
-        self.ui_send.send(UiEvent::Reset).unwrap();
+
+    /// Keeps the selected and the followed particles within the
+    /// particle counts, which change when particles die.
+    fn clamp_selected_particle(&mut self) {
+        let (c, p) = self.selected_particle;
+        self.selected_particle.1 = p.min(self.shared.particle_counts[c].saturating_sub(1));
+        if let FollowMode::Particle(c, p) = self.follow_mode {
+            self.follow_mode = match self.shared.particle_counts[c] {
+                0 => FollowMode::None,
+                count => FollowMode::Particle(c, p.min(count - 1)),
+            };
+        }
     }
-    fn spawn(&mut self) {
-        self.ui_send.send(UiEvent::Spawn).unwrap();
+
+    fn set_follow_mode(&mut self, follow_mode: FollowMode) {
+        // Keep the view where it is when we stop following.
+        if let Some(target) = self.follow_target() {
+            self.view.pos -= target;
+        }
+        self.follow_mode = follow_mode;
+        if self.follow_mode != FollowMode::None {
+            self.view.pos = Vec2::ZERO;
+        }
     }
-}
 
-impl App for Smarticles {
-    fn update(&mut self, ctx: &Context, frame: &mut Frame) {
-        if let Some(SimResults(elapsed, positions)) = self.sim_rcv.try_iter().last() {
-            if let Some(elapsed) = elapsed {
-                self.calculation_time = elapsed.as_millis();
-            }
-            self.particle_positions = positions;
+    /// Moves the opacity of each class toward its target and
+    /// sends the pending class count once the disabled classes
+    /// are fully faded out.
+    fn update_opacity(&mut self) {
+        for (c, opacity) in self.disabled_opacity.iter_mut().enumerate() {
+            *opacity = if c < self.shared.class_count {
+                (*opacity + FADE_RATE).min(1.)
+            } else {
+                (*opacity - FADE_RATE).max(0.)
+            };
         }
 
-        SidePanel::left("settings").show(ctx, |ui| {
-            ui.heading("settings");
-            ui.separator();
-            ui.horizontal(|ui| {
-                if ui
-                    .button("respawn")
-                    .on_hover_text("spawn particles again")
-                    .clicked()
-                {
-                    self.spawn();
+        if self.class_count_pending
+            && self.disabled_opacity[self.shared.class_count..]
+                .iter()
+                .all(|opacity| *opacity == 0.)
+        {
+            self.class_count_pending = false;
+            self.spawn();
+            self.send_class_count();
+        }
+    }
+
+    /// Draws the power matrix as a directed graph, nodes being
+    /// classes laid out on a circle. Clicking an edge scrolls to
+    /// the corresponding slider.
+    fn interaction_graph(&mut self, ui: &mut Ui) {
+        let (resp, paint) =
+            ui.allocate_painter(Vec2::splat(INTERACTION_GRAPH_SIZE), Sense::click());
+        let center = resp.rect.center();
+        let layout_radius = INTERACTION_GRAPH_SIZE / 2. - 3. * INTERACTION_GRAPH_NODE_RADIUS;
+        let class_count = self.shared.class_count;
+        let node_pos = |i: usize| {
+            center + layout_radius * Vec2::angled(TAU * i as f32 / class_count as f32 - FRAC_PI_2)
+        };
+
+        let click_pos = if resp.clicked() {
+            resp.interact_pointer_pos()
+        } else {
+            None
+        };
+        let mut clicked_edge = None;
+        let mut clicked_edge_distance = INTERACTION_GRAPH_CLICK_DISTANCE;
+
+        for i in 0..class_count {
+            for j in 0..class_count {
+                let force = self.shared.param_matrix[(i, j)].force;
+                if force == 0. {
+                    continue;
                 }
+                // The width already shows the strength.
+                let stroke = Stroke::new(
+                    0.5 + 3. * force.abs() / MAX_FORCE,
+                    force_color(force, force.abs()),
+                );
 
-                if self.shared.simulation_state == SimulationState::Running {
-                    if ui
-                        .button("pause")
-                        .on_hover_text("pause the simulation")
-                        .clicked()
-                    {
-                        self.pause();
+                let distance = if i == j {
+                    // Self interactions are drawn as loops outside
+                    // of the node.
+                    let loop_center = node_pos(i)
+                        + (node_pos(i) - center).normalized() * 1.5 * INTERACTION_GRAPH_NODE_RADIUS;
+                    paint.circle_stroke(loop_center, INTERACTION_GRAPH_NODE_RADIUS, stroke);
+                    click_pos.map(|pos| {
+                        (pos.distance(loop_center) - INTERACTION_GRAPH_NODE_RADIUS).abs()
+                    })
+                } else {
+                    // Edges are shifted sideways so that i -> j and
+                    // j -> i don't overlap.
+                    let dir = (node_pos(j) - node_pos(i)).normalized();
+                    let offset = 3. * dir.rot90();
+                    let start = node_pos(i) + dir * INTERACTION_GRAPH_NODE_RADIUS + offset;
+                    let end = node_pos(j) - dir * INTERACTION_GRAPH_NODE_RADIUS + offset;
+                    paint.arrow(start, end - start, stroke);
+                    click_pos.map(|pos| distance_to_segment(pos, start, end))
+                };
+
+                if let Some(distance) = distance {
+                    if distance < clicked_edge_distance {
+                        clicked_edge = Some((i, j));
+                        clicked_edge_distance = distance;
                     }
-                } else if ui
-                    .button("play")
-                    .on_hover_text("start the simulation")
-                    .clicked()
-                {
-                    self.play();
                 }
+            }
+        }
 
-                if ui
-                    .button("randomize")
-                    .on_hover_text("randomly pick a new seed")
-                    .clicked()
-                {
-                    let w1 = rand::random::<usize>() % self.words.len();
-                    let w2 = rand::random::<usize>() % self.words.len();
-                    let w3 = rand::random::<usize>() % self.words.len();
-                    self.seed = format!("{}_{}_{}", self.words[w1], self.words[w2], self.words[w3]);
+        for i in 0..class_count {
+            paint.circle_filled(
+                node_pos(i),
+                INTERACTION_GRAPH_NODE_RADIUS,
+                self.classes[i].color,
+            );
+        }
 
-                    self.update_history();
+        if let Some((i, j)) = clicked_edge {
+            // Only the sliders with i <= j are shown in symmetric
+            // mode.
+            let param = if self.symmetric_mode {
+                (i.min(j), i.max(j))
+            } else {
+                (i, j)
+            };
+            self.selected_param = param;
+            self.scroll_to_param = Some(param);
+        }
+    }
+
+    /// Particle of an enabled class closest to `pos`.
+    fn nearest_particle(&self, pos: Vec2) -> Option<(usize, usize)> {
+        (0..self.shared.class_count)
+            .flat_map(|c| (0..self.shared.particle_counts[c]).map(move |p| (c, p)))
+            .min_by(|a, b| {
+                let distance_a = (self.particle_positions[*a] - pos).length_sq();
+                let distance_b = (self.particle_positions[*b] - pos).length_sq();
+                distance_a.total_cmp(&distance_b)
+            })
+    }
+
+    fn inject_particle(&mut self, c: usize, pos: Vec2) {
+        if self.shared.particle_counts[c] >= MAX_PARTICLE_COUNT {
+            self.show_toast("this class is full");
+            return;
+        }
+        self.ui_send.send(UiEvent::InjectParticle(c, pos)).unwrap();
+        self.shared.particle_counts[c] += 1;
+        self.seed = self.export();
+    }
+
+    fn delete_particle(&mut self, (c, p): (usize, usize)) {
+        self.ui_send.send(UiEvent::DeleteParticle(c, p)).unwrap();
+        self.shared.particle_counts[c] -= 1;
+        self.seed = self.export();
+    }
+
+    /// Menu shown when right-clicking the canvas, the actions
+    /// apply at [`Smarticles::context_menu_pos`].
+    fn canvas_context_menu(&mut self, ui: &mut Ui) {
+        let pos = self.context_menu_pos;
+
+        if ui.button("select nearest particle").clicked() {
+            if let Some(particle) = self.nearest_particle(pos) {
+                self.selected_particle = particle;
+            }
+            ui.close_menu();
+        }
+        ui.menu_button("inject particle here", |ui| {
+            for c in 0..self.shared.class_count {
+                if ui.button(&self.classes[c].name).clicked() {
+                    self.inject_particle(c, pos);
+                    ui.close_menu();
+                }
+            }
+        });
+        if ui.button("delete nearest particle").clicked() {
+            if let Some(particle) = self.nearest_particle(pos) {
+                self.delete_particle(particle);
+            }
+            ui.close_menu();
+        }
+        if self.attractor_pinned {
+            if ui.button("remove attractor").clicked() {
+                self.attractor_pinned = false;
+                self.attractor.active = false;
+                self.ui_send
+                    .send(UiEvent::SetAttractor(self.attractor))
+                    .unwrap();
+                ui.close_menu();
+            }
+        } else if ui.button("set as attractor target").clicked() {
+            self.attractor_pinned = true;
+            self.attractor.active = true;
+            self.attractor.position = pos;
+            self.attractor.is_repulsor = false;
+            self.ui_send
+                .send(UiEvent::SetAttractor(self.attractor))
+                .unwrap();
+            ui.close_menu();
+        }
+        if ui.button("copy coordinates").clicked() {
+            ui.output().copied_text = format!("{:.1}, {:.1}", pos.x, pos.y);
+            self.show_toast("coordinates copied to clipboard");
+            ui.close_menu();
+        }
+    }
+
+    /// Draws the number of interacting particle pairs of each
+    /// pair of classes as a heatmap.
+    fn interaction_heatmap(&self, ui: &mut Ui) {
+        let class_count = self.shared.class_count;
+        let max_count = (0..class_count)
+            .flat_map(|i| (0..class_count).map(move |j| (i, j)))
+            .map(|(i, j)| self.interaction_counts[(i, j)])
+            .max()
+            .unwrap_or(0)
+            .max(1);
+
+        self.class_heatmap(
+            ui,
+            |i, j| {
+                let intensity = self.interaction_counts[(i, j)] as f32 / max_count as f32;
+                Color32::from_gray((20. + 235. * intensity) as u8)
+            },
+            |i, j| {
+                format!(
+                    "{} → {}: {} pairs",
+                    self.classes[j].name,
+                    self.classes[i].name,
+                    format_thousands(self.interaction_counts[(i, j)] as u64)
+                )
+            },
+        );
+    }
+
+    /// Color of each particle of the enabled classes depending on
+    /// the force its class gets from the class that has the most
+    /// particles within [`ADJACENCY_RANGE`] of it.
+    fn interaction_colors(&mut self) -> Vec<Vec<Color32>> {
+        self.interaction_grid
+            .fill(enabled_particles(&self.shared, &self.particle_positions));
+        let grid = &self.interaction_grid;
+
+        (0..self.shared.class_count)
+            .map(|c1| {
+                (0..self.shared.particle_counts[c1])
+                    .map(|p1| {
+                        let pos = self.particle_positions[(c1, p1)];
+                        let mut neighbor_counts = [0; MAX_CLASSES];
+                        for (c2, p2) in grid.neighbors(grid.cell_of(pos)) {
+                            if (c2, p2) != (c1, p1)
+                                && (self.particle_positions[(c2, p2)] - pos).length_sq()
+                                    < ADJACENCY_RANGE * ADJACENCY_RANGE
+                            {
+                                neighbor_counts[c2] += 1;
+                            }
+                        }
+                        let nearest_class = (0..self.shared.class_count)
+                            .filter(|c2| neighbor_counts[*c2] > 0)
+                            .max_by_key(|c2| neighbor_counts[*c2]);
+                        match nearest_class.map(|c2| self.shared.param_matrix[(c1, c2)].force) {
+                            Some(force) if force != 0. => force_color(force, force.abs()),
+                            _ => NO_INTERACTION_COLOR,
+                        }
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Lists the particles that act on the selected one (within
+    /// the action radius or the long-range zone past it), with the
+    /// velocity variation they give it. Clicking a particle selects
+    /// it.
+    fn nearby_particles(&mut self, ui: &mut Ui) {
+        let (c1, p1) = self.selected_particle;
+        let pos = self.particle_positions[(c1, p1)];
+        let mut nearby = Vec::new();
+        for c2 in 0..self.shared.class_count {
+            let param = &self.shared.param_matrix[(c1, c2)];
+            for p2 in 0..self.shared.particle_counts[c2] {
+                let distance = self.particle_positions[(c2, p2)] - pos;
+                if (c2, p2) == (c1, p1) || distance.length() >= param.radius + LONG_RANGE_LENGTH {
+                    continue;
+                }
+                let force = get_partial_velocity(
+                    distance,
+                    param.radius,
+                    -param.force * FORCE_FACTOR,
+                    -param.long_range_force * FORCE_FACTOR,
+                    self.close_force * FORCE_FACTOR,
+                ) / (FORCE_FACTOR * self.shared.class_masses[c1]);
+                nearby.push((c2, p2, distance.length(), force.length()));
+            }
+        }
+        nearby.sort_by(|a, b| a.2.total_cmp(&b.2));
+
+        if nearby.is_empty() {
+            ui.label("no particle within range");
+            return;
+        }
+        let mut selected = None;
+        ScrollArea::vertical()
+            .id_source("nearby particles")
+            .max_height(NEARBY_PARTICLES_HEIGHT)
+            .show(ui, |ui| {
+                Grid::new("nearby particles").striped(true).show(ui, |ui| {
+                    ui.label("class");
+                    ui.label("index");
+                    ui.label("distance");
+                    ui.label("force");
+                    ui.end_row();
+                    for (c, p, distance, force) in nearby {
+                        ui.colored_label(self.classes[c].color, &self.classes[c].heading);
+                        if ui
+                            .selectable_label(false, p.to_string())
+                            .on_hover_text("select this particle")
+                            .clicked()
+                        {
+                            selected = Some((c, p));
+                        }
+                        ui.code(format!("{:.1}", distance));
+                        ui.code(format!("{:.2}", force));
+                        ui.end_row();
+                    }
+                });
+            });
+        if let Some(particle) = selected {
+            self.selected_particle = particle;
+        }
+    }
+
+    /// Draws the number of adjacent particle pairs of each pair of
+    /// classes as a heatmap.
+    fn adjacency_heatmap(&self, ui: &mut Ui) {
+        let class_count = self.shared.class_count;
+        let max_count = (0..class_count)
+            .flat_map(|i| (0..class_count).map(move |j| (i, j)))
+            .map(|(i, j)| self.adjacency_counts[(i, j)])
+            .max()
+            .unwrap_or(0)
+            .max(1);
+
+        self.class_heatmap(
+            ui,
+            |i, j| {
+                let intensity = self.adjacency_counts[(i, j)] as f32 / max_count as f32;
+                Color32::from_rgb(20, 20, (20. + 235. * intensity) as u8)
+            },
+            |i, j| {
+                format!(
+                    "{} – {}: {} pairs",
+                    self.classes[i].name,
+                    self.classes[j].name,
+                    format_thousands(self.adjacency_counts[(i, j)] as u64)
+                )
+            },
+        );
+    }
+
+    /// Difference between the force of class j on class i and the
+    /// force of class i on class j.
+    fn force_asymmetry(&self, i: usize, j: usize) -> f32 {
+        self.shared.param_matrix[(i, j)].force - self.shared.param_matrix[(j, i)].force
+    }
+
+    /// Draws how unbalanced each pair of forces is as a heatmap,
+    /// green when the row class is the more attracted one.
+    fn asymmetry_heatmap(&self, ui: &mut Ui) {
+        let class_count = self.shared.class_count;
+        let max_asymmetry = (0..class_count)
+            .flat_map(|i| (0..class_count).map(move |j| (i, j)))
+            .map(|(i, j)| self.force_asymmetry(i, j).abs())
+            .fold(0., f32::max)
+            .max(f32::EPSILON);
+
+        self.class_heatmap(
+            ui,
+            |i, j| force_color(self.force_asymmetry(i, j), max_asymmetry),
+            |i, j| {
+                format!(
+                    "{} → {}: {:+.1}",
+                    self.classes[j].name,
+                    self.classes[i].name,
+                    self.force_asymmetry(i, j)
+                )
+            },
+        );
+    }
+
+    /// Draws the forces as a heatmap, green for attraction
+    /// (negative forces) and red for repulsion. Changes are
+    /// animated.
+    fn force_heatmap(&mut self, ui: &mut Ui) {
+        self.force_animation.update(self.force_snapshot());
+        let animation = &self.force_animation;
+
+        self.class_heatmap(
+            ui,
+            |i, j| force_color(animation.force(i, j), MAX_FORCE),
+            |i, j| {
+                format!(
+                    "{} → {}: {:.1}",
+                    self.classes[j].name,
+                    self.classes[i].name,
+                    self.shared.param_matrix[(i, j)].force
+                )
+            },
+        );
+    }
+
+    /// Draws a value for each pair of classes as a grid, row i
+    /// being the class that is affected like in the power matrix.
+    fn class_heatmap<C, H>(&self, ui: &mut Ui, cell_color: C, hover_text: H)
+    where
+        C: Fn(usize, usize) -> Color32,
+        H: Fn(usize, usize) -> String,
+    {
+        let class_count = self.shared.class_count;
+        let (resp, paint) =
+            ui.allocate_painter(Vec2::splat(INTERACTION_GRAPH_SIZE), Sense::hover());
+        // The first row and column show the class colors.
+        let cell_size = INTERACTION_GRAPH_SIZE / (class_count + 1) as f32;
+        let cell_rect = |row: usize, column: usize| {
+            Rect::from_min_size(
+                resp.rect.min + Vec2::new(column as f32, row as f32) * cell_size,
+                Vec2::splat(cell_size),
+            )
+            .shrink(1.)
+        };
+
+        let mut hovered = None;
+        for i in 0..class_count {
+            paint.rect_filled(cell_rect(i + 1, 0), 0., self.classes[i].color);
+            paint.rect_filled(cell_rect(0, i + 1), 0., self.classes[i].color);
+            for j in 0..class_count {
+                let rect = cell_rect(i + 1, j + 1);
+                paint.rect_filled(rect, 0., cell_color(i, j));
+                if resp.hover_pos().map_or(false, |pos| rect.contains(pos)) {
+                    hovered = Some((i, j));
+                }
+            }
+        }
+
+        if let Some((i, j)) = hovered {
+            resp.on_hover_text(hover_text(i, j));
+        }
+    }
+
+    /// Draws all the enabled particles scaled to fit the minimap
+    /// along with the current viewport. Clicking the minimap
+    /// moves the view there.
+    fn minimap(&mut self, ui: &mut Ui, canvas_rect: Rect, center: Pos2) {
+        let (resp, paint) = ui.allocate_painter(Vec2::splat(MINIMAP_SIZE), Sense::click_and_drag());
+        let rect = resp.rect;
+        let paint = paint.with_clip_rect(rect);
+        paint.rect_filled(rect, 0., Color32::from_rgba_unmultiplied(0, 0, 0, 200));
+        paint.rect_stroke(rect, 0., Stroke::new(1., Color32::GRAY));
+
+        let mut min = Vec2::splat(f32::INFINITY);
+        let mut max = Vec2::splat(f32::NEG_INFINITY);
+        for c in 0..self.shared.class_count {
+            for p in 0..self.shared.particle_counts[c] {
+                min = min.min(self.particle_positions[(c, p)]);
+                max = max.max(self.particle_positions[(c, p)]);
+            }
+        }
+        if min.x > max.x {
+            return;
+        }
+        let bbox_center = (min + max) / 2.;
+        let scale = MINIMAP_SIZE / (max - min).max_elem().max(1.);
+        let to_minimap = |pos: Vec2| rect.center() + (pos - bbox_center) * scale;
+
+        for c in 0..self.shared.class_count {
+            for p in 0..self.shared.particle_counts[c] {
+                paint.rect_filled(
+                    Rect::from_center_size(
+                        to_minimap(self.particle_positions[(c, p)]),
+                        Vec2::splat(1.),
+                    ),
+                    0.,
+                    self.classes[c].color,
+                );
+            }
+        }
+
+        let viewport = Rect::from_two_pos(
+            to_minimap((canvas_rect.min - center) / self.view.zoom),
+            to_minimap((canvas_rect.max - center) / self.view.zoom),
+        );
+        paint.rect_stroke(viewport, 0., Stroke::new(1., Color32::WHITE));
+
+        if resp.clicked() || resp.dragged() {
+            if let Some(pointer_pos) = resp.interact_pointer_pos() {
+                let target = bbox_center + (pointer_pos - rect.center()) / scale;
+                self.view.pos = self.follow_target().unwrap_or(Vec2::ZERO) - target;
+            }
+        }
+    }
+
+    fn save_preset(&mut self) {
+        let preset = Preset {
+            name: self.preset_name.trim().to_string(),
+            seed: self.seed.to_owned(),
+            class_count: self.shared.class_count,
+            particle_counts: self.shared.particle_counts,
+            zoom: self.view.zoom,
+            view_pos: [self.view.pos.x, self.view.pos.y],
+            class_names: self.classes.iter().map(|c| c.name.to_owned()).collect(),
+            class_colors: self.classes.iter().map(|c| c.color.to_array()).collect(),
+        };
+        match self.presets.iter().position(|p| p.name == preset.name) {
+            Some(i) => {
+                self.presets[i] = preset;
+                self.selected_preset = i;
+            }
+            None => {
+                self.presets.push(preset);
+                self.selected_preset = self.presets.len() - 1;
+            }
+        }
+        storage::save_presets(&self.presets);
+    }
+
+    fn load_preset(&mut self) {
+        let preset = self.presets[self.selected_preset].to_owned();
+
+        self.seed = preset.seed;
+        self.update_history();
+        self.apply_seed();
+
+        self.shared.class_count = preset.class_count.min(class_limit());
+        self.shared.particle_counts = preset
+            .particle_counts
+            .map(|count| count.min(MAX_PARTICLE_COUNT));
+        self.send_class_count();
+        self.send_particle_counts();
+        self.spawn();
+
+        self.set_follow_mode(FollowMode::None);
+        self.view.zoom = preset.zoom;
+        self.view.pos = Vec2::new(preset.view_pos[0], preset.view_pos[1]);
+
+        for (c, name) in preset.class_names.into_iter().enumerate().take(MAX_CLASSES) {
+            self.rename_class(c, name);
+        }
+        for (c, [r, g, b, _]) in preset
+            .class_colors
+            .into_iter()
+            .enumerate()
+            .take(MAX_CLASSES)
+        {
+            self.classes[c].color = Color32::from_rgb(r, g, b);
+        }
+    }
+
+    fn delete_preset(&mut self) {
+        self.presets.remove(self.selected_preset);
+        self.selected_preset = self
+            .selected_preset
+            .min(self.presets.len().saturating_sub(1));
+        storage::save_presets(&self.presets);
+    }
+
+    /// Checks every [`WATCHDOG_CHECK_INTERVAL`] that the
+    /// simulation thread is still updating.
+    fn check_simulation_heartbeat(&mut self) {
+        if self.last_watchdog_check.elapsed() < WATCHDOG_CHECK_INTERVAL {
+            return;
+        }
+        self.last_watchdog_check = Instant::now();
+
+        let heartbeat_age = Duration::from_millis(
+            epoch_millis().saturating_sub(self.heartbeat.load(Ordering::Relaxed)),
+        );
+        // Slow updates are not a reason to worry.
+        let expected_interval = UPDATE_INTERVAL
+            .max(PAUSED_UPDATE_INTERVAL)
+            .max(Duration::from_millis(self.calculation_time as u64));
+        self.simulation_unresponsive = heartbeat_age > 3 * expected_interval;
+    }
+
+    /// Replaces the simulation thread with a new one and sends it
+    /// the current state. The old thread can't be killed, it is
+    /// left on its own (and stops as soon as it tries to send
+    /// something).
+    fn restart_simulation(&mut self, ctx: &Context) {
+        let _ = self.ui_send.send(UiEvent::Quit);
+
+        let simulation = spawn_simulation(ctx.clone());
+        self.ui_send = simulation.ui_send;
+        self.sim_rcv = simulation.sim_rcv;
+        self.positions_buffer = simulation.positions_buffer;
+        self.heartbeat = simulation.heartbeat;
+        if let Some(handle) = self.simulation_handle.replace(simulation.handle) {
+            if handle.is_finished() {
+                let _ = handle.join();
+            }
+        }
+
+        self.send_params();
+        self.send_class_count();
+        self.send_particle_counts();
+        self.send_class_masses();
+        self.send_population_rates();
+        self.send_thread_count();
+        self.send_temperature();
+        self.send_gravity();
+        self.send_vortex_strength();
+        self.send_boundary();
+        self.send_max_particles_per_cell();
+        self.send_spawn_density();
+        self.send_close_force();
+        self.send_spawn_shape();
+        self.send_show_kinetic_energy();
+        self.send_count_adjacencies();
+        for (c, frozen) in self.frozen_classes.iter().enumerate() {
+            if *frozen {
+                self.send_frozen_class(c);
+            }
+        }
+        if self.attractor_pinned {
+            self.ui_send
+                .send(UiEvent::SetAttractor(self.attractor))
+                .unwrap();
+        }
+        self.spawn();
+        if self.basin_buffer.is_some() {
+            self.send_basin_explorer(true);
+        }
+        if self.shared.simulation_state == SimulationState::Running {
+            self.ui_send.send(UiEvent::Play).unwrap();
+        }
+
+        self.simulation_unresponsive = false;
+        self.last_watchdog_check = Instant::now();
+    }
+
+    /// Picks a seed made of three random words.
+    fn random_seed(&self) -> String {
+        let w1 = rand::random::<usize>() % self.words.len();
+        let w2 = rand::random::<usize>() % self.words.len();
+        let w3 = rand::random::<usize>() % self.words.len();
+        format!("{}_{}_{}", self.words[w1], self.words[w2], self.words[w3])
+    }
+
+    /// Starts a second simulation with the comparison seed and the
+    /// same settings as the main one.
+    fn start_comparison(&mut self, ctx: &Context) {
+        self.stop_comparison();
+        if self.comparison_seed.is_empty() {
+            self.comparison_seed = self.random_seed();
+        }
+
+        let comparison_seed = self.comparison_seed.to_owned();
+        let comparison_shared = self.decode_seed(&comparison_seed);
+
+        let simulation = spawn_simulation(ctx.clone());
+        for event in self.simulation_events(&comparison_shared) {
+            simulation.ui_send.send(event).unwrap();
+        }
+        if self.shared.simulation_state == SimulationState::Running {
+            simulation.ui_send.send(UiEvent::Play).unwrap();
+        }
+
+        self.comparison = Some(Comparison {
+            seed: comparison_seed,
+            shared: comparison_shared,
+            particle_positions: Array2D::filled_with(Vec2::ZERO, MAX_CLASSES, MAX_PARTICLE_COUNT),
+            ui_send: simulation.ui_send,
+            sim_rcv: simulation.sim_rcv,
+            positions_buffer: simulation.positions_buffer,
+        });
+    }
+
+    /// Events that set up a new simulation with the given shared
+    /// state and the current settings, then spawn its particles.
+    fn simulation_events(&self, shared: &SharedState) -> Vec<UiEvent> {
+        vec![
+            UiEvent::ParamsUpdate(shared.param_matrix.to_owned()),
+            UiEvent::ClassCountUpdate(shared.class_count),
+            UiEvent::ParticleCountsUpdate(shared.particle_counts),
+            UiEvent::ClassMassesUpdate(shared.class_masses),
+            UiEvent::PopulationRatesUpdate(shared.birth_rates, shared.death_rates),
+            UiEvent::SetTemperature(self.temperature),
+            UiEvent::SetGravity(self.gravity()),
+            UiEvent::SetVortexStrength(self.vortex_strength),
+            UiEvent::SetBoundary(self.boundary, self.restitution),
+            UiEvent::SetMaxParticlesPerCell(self.max_particles_per_cell),
+            UiEvent::SetSpawnDensity(self.spawn_density),
+            UiEvent::SetCloseForce(self.close_force),
+            UiEvent::SetSpawnShape(self.spawn_shape),
+            UiEvent::Spawn,
+        ]
+    }
+
+    fn stop_comparison(&mut self) {
+        if let Some(comparison) = self.comparison.take() {
+            let _ = comparison.ui_send.send(UiEvent::Quit);
+        }
+    }
+
+    /// Sends the event to the simulation and to the comparison if
+    /// there is one, used for play/pause/reset/spawn/quit so that
+    /// both simulations stay in sync.
+    fn broadcast(&self, event: UiEvent) {
+        if let Some(comparison) = &self.comparison {
+            comparison.ui_send.send(event.clone()).unwrap();
+        }
+        self.ui_send.send(event).unwrap();
+    }
+
+    fn start_recording(&mut self) {
+        self.stop_playback();
+        self.recording = Some(Recording::new(self.shared.particle_counts));
+        self.is_recording = true;
+    }
+
+    fn record_frame(&mut self) {
+        let recording = match &mut self.recording {
+            Some(recording) => recording,
+            None => return,
+        };
+        if recording.particle_counts != self.shared.particle_counts {
+            self.is_recording = false;
+            self.show_toast("particle counts changed, recording stopped");
+        } else if !recording.push_frame(&self.particle_positions) {
+            self.is_recording = false;
+            self.show_toast("recording is full");
+        }
+    }
+
+    /// Pauses the simulation and shows the recorded frames
+    /// instead.
+    fn start_playback(&mut self) {
+        let particle_counts = match &self.recording {
+            Some(recording) if !recording.is_empty() => recording.particle_counts,
+            _ => return,
+        };
+        self.is_recording = false;
+        if self.shared.simulation_state == SimulationState::Running {
+            self.pause();
+        }
+
+        if self.shared.particle_counts != particle_counts {
+            self.shared.particle_counts = particle_counts;
+            self.send_particle_counts();
+            self.spawn();
+        }
+
+        self.playback_frame = Some(0);
+        self.playback_playing = true;
+        self.last_playback_step = Instant::now();
+        if let Some(recording) = &self.recording {
+            recording.copy_frame(0, &mut self.particle_positions);
+        }
+    }
+
+    /// Shows the next recorded frame, frames are shown at the
+    /// simulation update rate.
+    fn step_playback(&mut self) {
+        let (frame, recording) = match (self.playback_frame, &self.recording) {
+            (Some(frame), Some(recording)) => (frame, recording),
+            _ => return,
+        };
+        if self.last_playback_step.elapsed() < UPDATE_INTERVAL {
+            return;
+        }
+        self.last_playback_step = Instant::now();
+
+        if frame + 1 < recording.len() {
+            recording.copy_frame(frame + 1, &mut self.particle_positions);
+            self.playback_frame = Some(frame + 1);
+        } else {
+            self.playback_playing = false;
+        }
+    }
+
+    fn stop_playback(&mut self) {
+        self.playback_frame = None;
+        self.playback_playing = false;
+        copy_particle_positions(
+            &self.positions_buffer.read().unwrap(),
+            &mut self.particle_positions,
+            &self.shared.particle_counts,
+        );
+    }
+
+    fn save_recording(&mut self) {
+        let recording = match &self.recording {
+            Some(recording) => recording,
+            None => return,
+        };
+        let path = match FileDialog::new()
+            .add_filter("recording", &["smrec"])
+            .set_file_name("recording.smrec")
+            .save_file()
+        {
+            Some(path) => path,
+            None => return,
+        };
+
+        match recording.save(&path) {
+            Ok(_) => self.show_toast("recording saved"),
+            Err(err) => self.show_toast(format!("failed to save recording: {}", err)),
+        }
+    }
+
+    fn load_recording(&mut self) {
+        let path = match FileDialog::new()
+            .add_filter("recording", &["smrec"])
+            .pick_file()
+        {
+            Some(path) => path,
+            None => return,
+        };
+
+        match Recording::load(&path) {
+            Ok(recording) => {
+                self.stop_playback();
+                self.is_recording = false;
+                self.recording = Some(recording);
+                self.show_toast("recording loaded");
+            }
+            Err(err) => self.show_toast(format!("failed to load recording: {}", err)),
+        }
+    }
+
+    /// Saves the particles as shown on the canvas as an SVG image.
+    fn export_svg(&mut self) {
+        let path = match FileDialog::new()
+            .add_filter("svg", &["svg"])
+            .set_file_name("smarticles.svg")
+            .save_file()
+        {
+            Some(path) => path,
+            None => return,
+        };
+
+        let colors: Vec<Color32> = self.classes[..self.shared.class_count]
+            .iter()
+            .map(|class| class.color)
+            .collect();
+        let svg = export_as_svg(
+            &self.particle_positions,
+            &self.shared.particle_counts,
+            &colors,
+            self.view.pos - self.follow_target().unwrap_or(Vec2::ZERO),
+            self.view.zoom,
+            self.canvas_size,
+        );
+        match fs::write(&path, svg) {
+            Ok(_) => self.show_toast("SVG exported"),
+            Err(err) => self.show_toast(format!("failed to export SVG: {}", err)),
+        }
+    }
+
+    fn save_state(&mut self) {
+        let path = match FileDialog::new()
+            .add_filter("simulation state", &["json"])
+            .set_file_name("state.json")
+            .save_file()
+        {
+            Some(path) => path,
+            None => return,
+        };
+
+        // The simulation writes the file, the outcome is picked up
+        // in [`Smarticles::update`].
+        let (save_send, save_rcv) = channel();
+        self.ui_send
+            .send(UiEvent::SaveState(path, save_send))
+            .unwrap();
+        self.save_rcv = Some(save_rcv);
+    }
+
+    fn load_state(&mut self) {
+        let path = match FileDialog::new()
+            .add_filter("simulation state", &["json"])
+            .pick_file()
+        {
+            Some(path) => path,
+            None => return,
+        };
+
+        let value = match fs::read_to_string(&path)
+            .map_err(|err| err.to_string())
+            .and_then(|json| serde_json::from_str::<Value>(&json).map_err(|err| err.to_string()))
+        {
+            Ok(value) => value,
+            Err(err) => {
+                self.show_toast(format!("failed to load state: {}", err));
+                return;
+            }
+        };
+        let snapshot = match serde_json::from_value::<SimulationSnapshot>(value.to_owned()) {
+            Ok(snapshot) => snapshot,
+            Err(err) => {
+                self.show_toast(format!("failed to load state: {}", err));
+                return;
+            }
+        };
+        if let Err(err) = snapshot.validate() {
+            self.show_toast(format!("failed to load state: {}", err));
+            return;
+        }
+
+        self.stop_playback();
+        self.shared = snapshot.shared;
+        self.shared.class_count = self.shared.class_count.min(class_limit());
+        self.temperature = snapshot.temperature;
+        self.gravity_angle = snapshot.gravity.angle().to_degrees();
+        self.gravity_magnitude = snapshot.gravity.length();
+        self.vortex_strength = snapshot.vortex_strength;
+        self.boundary = snapshot.boundary;
+        self.restitution = snapshot.restitution;
+        self.max_particles_per_cell = snapshot.max_particles_per_cell;
+        self.spawn_density = snapshot.spawn_density;
+        self.close_force = snapshot.close_force;
+        self.spawn_shape = snapshot.spawn_shape;
+        self.frozen_classes = snapshot.frozen_classes;
+        self.seed = self.export();
+        self.update_history();
+
+        self.ui_send.send(UiEvent::LoadState(value)).unwrap();
+        self.send_class_count();
+        self.show_toast("state loaded");
+        self.check_symmetric_mode();
+    }
+
+    fn save_window_geometry(&self) {
+        if let Some(geometry) = &self.window_geometry {
+            storage::save_window_geometry(geometry);
+        }
+    }
+
+    fn update_kinetic_energy(
+        &mut self,
+        particle_velocities: &Array2D<Vec2>,
+        particle_counts: &[usize; MAX_CLASSES],
+    ) {
+        self.kinetic_energy = Some(
+            (0..self.shared.class_count)
+                .map(|c| {
+                    0.5 * self.shared.class_masses[c]
+                        * (0..particle_counts[c])
+                            .map(|p| particle_velocities[(c, p)].length_sq())
+                            .sum::<f32>()
+                })
+                .sum(),
+        );
+    }
+
+    fn send_show_kinetic_energy(&self) {
+        self.ui_send
+            .send(UiEvent::SetSendVelocities(self.show_kinetic_energy))
+            .unwrap();
+    }
+
+    fn send_count_adjacencies(&self) {
+        self.ui_send
+            .send(UiEvent::SetCountAdjacencies(self.count_adjacencies))
+            .unwrap();
+    }
+
+    fn update_order_parameter(&mut self, order_parameter: f32) {
+        let was_ordered = self.order_parameter >= self.order_threshold;
+        self.order_parameter = order_parameter;
+
+        self.order_parameter_history.push_back(order_parameter);
+        if self.order_parameter_history.len() > ORDER_PARAMETER_HISTORY_LEN {
+            self.order_parameter_history.pop_front();
+        }
+
+        if self.auto_save_ordered && !was_ordered && order_parameter >= self.order_threshold {
+            self.seed = self.export();
+            self.update_history();
+            self.show_toast("ordered state reached, seed saved to the history");
+        }
+    }
+
+    /// Computes the pattern score once [`PATTERN_SCORE_TICK`] is
+    /// reached and flags the seed if the particles are structured
+    /// enough, the seed is copied to the clipboard the first time.
+    fn update_pattern_score(&mut self, ctx: &Context) {
+        if self.tick_count < PATTERN_SCORE_TICK {
+            self.pattern_score = None;
+            return;
+        }
+        if self.pattern_score.is_some() {
+            return;
+        }
+        self.pattern_score = pattern_score(&self.shared, &self.particle_positions);
+
+        if let Some(score) = self.pattern_score {
+            if score < self.pattern_threshold {
+                let seed = self.export();
+                if !self.flagged_seeds.iter().any(|(s, _)| *s == seed) {
+                    ctx.output().copied_text = seed.to_owned();
+                    self.flagged_seeds.push((seed, score));
+                    self.show_toast("structured pattern, seed copied to clipboard");
+                }
+            }
+        }
+    }
+
+    /// Shows a short notification on top of the canvas.
+    fn show_toast<S: ToString>(&mut self, message: S) {
+        self.toast = Some((message.to_string(), Instant::now()));
+    }
+
+    /// Copy of the current forces.
+    fn force_snapshot(&self) -> Array2D<f32> {
+        Array2D::from_iter_row_major(
+            self.shared
+                .param_matrix
+                .elements_row_major_iter()
+                .map(|p| p.force),
+            MAX_CLASSES,
+            MAX_CLASSES,
+        )
+        .unwrap()
+    }
+
+    /// How much each force moved since `snapshot` was taken.
+    fn force_delta(&self, snapshot: &Array2D<f32>) -> Array2D<f32> {
+        Array2D::from_iter_row_major(
+            self.shared
+                .param_matrix
+                .elements_row_major_iter()
+                .zip(snapshot.elements_row_major_iter())
+                .map(|(p, force)| p.force - force),
+            MAX_CLASSES,
+            MAX_CLASSES,
+        )
+        .unwrap()
+    }
+
+    fn export_forces_csv(&mut self) {
+        let path = match FileDialog::new()
+            .add_filter("csv", &["csv"])
+            .set_file_name("forces.csv")
+            .save_file()
+        {
+            Some(path) => path,
+            None => return,
+        };
+
+        match fs::write(path, storage::export_matrix_csv(&self.force_snapshot())) {
+            Ok(_) => self.show_toast("forces exported"),
+            Err(err) => self.show_toast(format!("failed to export forces: {}", err)),
+        }
+    }
+
+    /// Runs random seeds with the current settings in a
+    /// background thread, the ranked results are picked up in
+    /// [`Smarticles::update`].
+    fn explore_seeds(&mut self, ctx: &Context) {
+        let seeds = (0..self.batch_seed_count)
+            .map(|_| {
+                let seed = self.random_seed();
+                let shared = self.decode_seed(&seed);
+                let events = self.simulation_events(&shared);
+                (seed, events)
+            })
+            .collect();
+        self.batch_stop = Arc::new(AtomicBool::new(false));
+        let runner = BatchSeedRunner::new(
+            seeds,
+            self.batch_tick_count,
+            self.batch_metric,
+            self.batch_stop.clone(),
+        );
+
+        let (batch_send, batch_rcv) = channel();
+        let ctx = ctx.clone();
+        thread::spawn(move || {
+            // The ui may have been closed in the meantime.
+            let _ = batch_send.send(runner.run());
+            ctx.request_repaint();
+        });
+        self.batch_rcv = Some(batch_rcv);
+    }
+
+    fn export_batch_csv(&mut self) {
+        let path = match FileDialog::new()
+            .add_filter("csv", &["csv"])
+            .set_file_name("seeds.csv")
+            .save_file()
+        {
+            Some(path) => path,
+            None => return,
+        };
+
+        match fs::write(path, batch::results_csv(&self.batch_results)) {
+            Ok(_) => self.show_toast("seeds exported"),
+            Err(err) => self.show_toast(format!("failed to export seeds: {}", err)),
+        }
+    }
+
+    fn export_adjacency_csv(&mut self) {
+        let path = match FileDialog::new()
+            .add_filter("csv", &["csv"])
+            .set_file_name("adjacency.csv")
+            .save_file()
+        {
+            Some(path) => path,
+            None => return,
+        };
+
+        match fs::write(path, storage::export_matrix_csv(&self.adjacency_counts)) {
+            Ok(_) => self.show_toast("adjacency matrix exported"),
+            Err(err) => self.show_toast(format!("failed to export adjacency matrix: {}", err)),
+        }
+    }
+
+    fn import_forces_csv(&mut self) {
+        let path = match FileDialog::new().add_filter("csv", &["csv"]).pick_file() {
+            Some(path) => path,
+            None => return,
+        };
+
+        let forces = match fs::read_to_string(path) {
+            Ok(csv) => storage::import_force_matrix_csv(&csv).map_err(|err| err.to_string()),
+            Err(err) => Err(err.to_string()),
+        };
+        match forces {
+            Ok(forces) => {
+                self.push_undo(self.undo_entry());
+                for i in 0..MAX_CLASSES {
+                    for j in 0..MAX_CLASSES {
+                        self.shared.param_matrix[(i, j)].force =
+                            forces[(i, j)].clamp(MIN_FORCE, MAX_FORCE);
+                    }
+                }
+                self.seed = self.export();
+                self.send_params();
+                self.show_toast("forces imported");
+                self.check_symmetric_mode();
+            }
+            Err(err) => self.show_toast(format!("failed to import forces: {}", err)),
+        }
+    }
+
+    /// Draws a line between each pair of particles closer than
+    /// the action radius of their params, when at least one of
+    /// them is visible.
+    fn draw_connections(&mut self, paint: &Painter, center: Pos2) {
+        // No action radius is larger than the cells, so the
+        // neighboring cells hold all the particles in range.
+        self.connection_grid
+            .fill(enabled_particles(&self.shared, &self.particle_positions));
+        let grid = &self.connection_grid;
+
+        let clip_rect = paint.clip_rect();
+        let screen_pos =
+            |c: usize, p: usize| center + self.particle_positions[(c, p)] * self.view.zoom;
+
+        let mut line_count = 0;
+        for c1 in 0..self.shared.class_count {
+            for p1 in 0..self.shared.particle_counts[c1] {
+                let pos1 = screen_pos(c1, p1);
+                if !clip_rect.contains(pos1) {
+                    continue;
+                }
+                let cell = grid.cell_of(self.particle_positions[(c1, p1)]);
+                for (c2, p2) in grid.neighbors(cell) {
+                    let pos2 = screen_pos(c2, p2);
+                    // Pairs of visible particles are drawn once.
+                    if clip_rect.contains(pos2) && (c2, p2) <= (c1, p1) {
+                        continue;
+                    }
+                    let radius = self.shared.param_matrix[(c1, c2)].radius;
+                    let distance = (self.particle_positions[(c2, p2)]
+                        - self.particle_positions[(c1, p1)])
+                        .length();
+                    if distance >= radius {
+                        continue;
+                    }
+
+                    let color = blend_colors(self.classes[c1].color, self.classes[c2].color);
+                    let opacity = CONNECTION_LINE_OPACITY * (1. - distance / radius);
+                    paint.line_segment(
+                        [pos1, pos2],
+                        Stroke::new(1., color.linear_multiply(opacity)),
+                    );
+                    line_count += 1;
+                    if line_count >= MAX_CONNECTION_LINES {
+                        return;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Makes the forces symmetric by copying the upper half of
+    /// the matrix to the lower half.
+    fn symmetrize_forces(&mut self) {
+        for i in 0..MAX_CLASSES {
+            for j in 0..i {
+                self.shared.param_matrix[(i, j)].force = self.shared.param_matrix[(j, i)].force;
+                self.shared.param_matrix[(i, j)].long_range_force =
+                    self.shared.param_matrix[(j, i)].long_range_force;
+            }
+        }
+        self.seed = self.export();
+        self.send_params();
+    }
+
+    /// Turns the symmetric mode off when the forces that were just
+    /// loaded aren't symmetric, the hidden sliders would hide the
+    /// differences otherwise.
+    fn check_symmetric_mode(&mut self) {
+        let class_count = self.shared.class_count;
+        let matrix = &self.shared.param_matrix;
+        let symmetric = (0..class_count).all(|i| {
+            (0..i).all(|j| {
+                matrix[(i, j)].force == matrix[(j, i)].force
+                    && matrix[(i, j)].long_range_force == matrix[(j, i)].long_range_force
+            })
+        });
+        if self.symmetric_mode && !symmetric {
+            self.symmetric_mode = false;
+            self.show_toast("symmetric forces turned off, the new forces aren't symmetric");
+        }
+    }
+
+    fn undo_entry(&self) -> UndoEntry {
+        UndoEntry {
+            param_matrix: self.shared.param_matrix.to_owned(),
+            particle_counts: self.shared.particle_counts,
+        }
+    }
+
+    /// Saves `entry` as an undo step, clearing the redo steps.
+    fn push_undo(&mut self, entry: UndoEntry) {
+        self.undo_stack.push(entry);
+        if self.undo_stack.len() > MAX_UNDO_LEN {
+            self.undo_stack.remove(0);
+        }
+        self.redo_stack.clear();
+    }
+
+    fn undo(&mut self) {
+        if let Some(entry) = self.undo_stack.pop() {
+            self.redo_stack.push(self.undo_entry());
+            self.restore_undo_entry(entry);
+        }
+    }
+
+    fn redo(&mut self) {
+        if let Some(entry) = self.redo_stack.pop() {
+            self.undo_stack.push(self.undo_entry());
+            self.restore_undo_entry(entry);
+        }
+    }
+
+    fn restore_undo_entry(&mut self, entry: UndoEntry) {
+        self.shared.param_matrix = entry.param_matrix;
+        self.shared.particle_counts = entry.particle_counts;
+        self.seed = self.export();
+        self.check_symmetric_mode();
+
+        self.send_params();
+        self.send_particle_counts();
+        self.spawn();
+    }
+
+    /// Renames a class, the name is only used by the ui.
+    fn rename_class(&mut self, c: usize, name: String) {
+        let name = name.trim();
+        if name.is_empty() {
+            self.class_name_edits[c] = self.classes[c].name.to_owned();
+            return;
+        }
+        self.classes[c].name = name.to_string();
+        self.classes[c].heading = "class ".to_string() + name;
+        self.class_name_edits[c] = name.to_string();
+    }
+
+    fn update_history(&mut self) {
+        self.history.push_front(self.seed.to_owned());
+        self.history.truncate(MAX_SAVED_HISTORY_LEN);
+        self.selected_history_entry = 0;
+
+        storage::save_history(&self.history);
+    }
+}
+
+impl UpdateSharedState for Smarticles {
+    fn play(&mut self) {
+        self.shared.simulation_state = SimulationState::Running;
+        self.broadcast(UiEvent::Play);
+    }
+    fn pause(&mut self) {
+        self.shared.simulation_state = SimulationState::Paused;
+        self.broadcast(UiEvent::Pause);
+    }
+    fn reset(&mut self) {
+        self.shared.simulation_state = SimulationState::Stopped;
+        self.broadcast(UiEvent::Reset);
+    }
+    fn spawn(&mut self) {
+        self.broadcast(UiEvent::Spawn);
+    }
+}
+
+impl App for Smarticles {
+    fn update(&mut self, ctx: &Context, frame: &mut Frame) {
+        if self.windowed {
+            let window_info = &frame.info().window_info;
+            self.window_geometry = Some(WindowGeometry {
+                position: window_info.position,
+                size: window_info.size,
+            });
+        }
+
+        let now = Instant::now();
+        if let Some(last_frame_time) = self.last_frame_time {
+            self.frame_durations
+                .push_back((now - last_frame_time).as_secs_f32());
+            if self.frame_durations.len() > FPS_SAMPLE_COUNT {
+                self.frame_durations.pop_front();
+            }
+        }
+        self.last_frame_time = Some(now);
+
+        if let Some(SimResults {
+            elapsed,
+            tick_count,
+            particle_counts,
+            order_parameter,
+            interaction_counts,
+            adjacency_counts,
+            perf_breakdown,
+            particle_velocities,
+        }) = self.sim_rcv.try_iter().last()
+        {
+            if let Some(particle_velocities) = particle_velocities {
+                self.update_kinetic_energy(&particle_velocities, &particle_counts);
+            }
+            self.perf_breakdown = perf_breakdown;
+            self.tick_count = tick_count;
+            self.interaction_counts = interaction_counts;
+            self.adjacency_counts = adjacency_counts;
+            // Only births and deaths change the counts on the
+            // simulation side.
+            if self.population_changes() {
+                self.shared.particle_counts = particle_counts;
+                self.clamp_selected_particle();
+            }
+            self.update_order_parameter(order_parameter);
+            if let Some(elapsed) = elapsed {
+                self.calculation_time = elapsed.as_millis();
+
+                self.calculation_time_history.push_back(elapsed);
+                if self.calculation_time_history.len() > CALCULATION_TIME_HISTORY_LEN {
+                    self.calculation_time_history.pop_front();
+                }
+                self.peak_calculation_time = self.peak_calculation_time.max(elapsed);
+            }
+            if self.playback_frame.is_none() {
+                copy_particle_positions(
+                    &self.positions_buffer.read().unwrap(),
+                    &mut self.particle_positions,
+                    &self.shared.particle_counts,
+                );
+                if self.is_recording {
+                    self.record_frame();
+                }
+                self.update_pattern_score(ctx);
+            }
+        }
+        if let Some(save_rcv) = &self.save_rcv {
+            match save_rcv.try_recv() {
+                Ok(Ok(())) => {
+                    self.save_rcv = None;
+                    self.show_toast("state saved");
+                }
+                Ok(Err(err)) => {
+                    self.save_rcv = None;
+                    self.show_toast(format!("failed to save state: {}", err));
+                }
+                Err(TryRecvError::Disconnected) => {
+                    self.save_rcv = None;
+                    self.show_toast("failed to save state");
+                }
+                Err(TryRecvError::Empty) => (),
+            }
+        }
+        if let Some(batch_rcv) = &self.batch_rcv {
+            match batch_rcv.try_recv() {
+                Ok(results) => {
+                    self.batch_results = results;
+                    self.batch_rcv = None;
+                    if self.batch_stop.load(Ordering::Relaxed) {
+                        self.show_toast("seed exploration stopped");
+                    } else {
+                        self.show_toast("seed exploration done");
+                    }
+                }
+                Err(TryRecvError::Disconnected) => {
+                    self.batch_rcv = None;
+                    self.show_toast("seed exploration failed");
+                }
+                Err(TryRecvError::Empty) => (),
+            }
+        }
+        if let Some(comparison) = &mut self.comparison {
+            if let Some(results) = comparison.sim_rcv.try_iter().last() {
+                comparison.shared.particle_counts = results.particle_counts;
+                copy_particle_positions(
+                    &comparison.positions_buffer.read().unwrap(),
+                    &mut comparison.particle_positions,
+                    &comparison.shared.particle_counts,
+                );
+            }
+        }
+        if self.playback_playing {
+            self.step_playback();
+            ctx.request_repaint();
+        }
+
+        self.update_opacity();
+        self.check_simulation_heartbeat();
+
+        if self.simulation_unresponsive {
+            Window::new("simulation")
+                .collapsible(false)
+                .resizable(false)
+                .anchor(Align2::CENTER_CENTER, [0., 0.])
+                .show(ctx, |ui| {
+                    ui.label("simulation thread appears unresponsive");
+                    ui.horizontal(|ui| {
+                        if ui.button("kill and restart").clicked() {
+                            self.restart_simulation(ctx);
+                        }
+                        if ui.button("wait").clicked() {
+                            self.simulation_unresponsive = false;
+                            self.last_watchdog_check = Instant::now();
+                        }
+                    });
+                });
+        }
+
+        if self.awaiting_paste {
+            let pasted = ctx.input().events.iter().find_map(|e| {
+                if let egui::Event::Paste(s) = e {
+                    Some(s.to_owned())
+                } else {
+                    None
+                }
+            });
+            if let Some(seed) = pasted {
+                self.awaiting_paste = false;
+                self.seed = seed.trim().to_string();
+
+                self.update_history();
+
+                self.apply_seed();
+                self.spawn();
+
+                self.show_toast("seed pasted");
+            }
+        }
+
+        // Text fields have their own undo.
+        if !ctx.wants_keyboard_input() {
+            let (undo, redo) = {
+                let input = ctx.input();
+                let z_pressed = input.modifiers.command && input.key_pressed(Key::Z);
+                (
+                    z_pressed && !input.modifiers.shift,
+                    z_pressed && input.modifiers.shift,
+                )
+            };
+            if undo {
+                self.undo();
+            } else if redo {
+                self.redo();
+            }
+        }
+
+        SidePanel::left("settings").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.heading("settings");
+
+                let frame_duration_sum: f32 = self.frame_durations.iter().sum();
+                if frame_duration_sum > 0. {
+                    ui.label(format!(
+                        "FPS: {:.1}",
+                        self.frame_durations.len() as f32 / frame_duration_sum
+                    ));
+                }
+            });
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui
+                    .button("respawn")
+                    .on_hover_text("spawn particles again")
+                    .clicked()
+                {
+                    self.spawn();
+                }
+
+                if self.shared.simulation_state == SimulationState::Running {
+                    if ui
+                        .button("pause")
+                        .on_hover_text("pause the simulation")
+                        .clicked()
+                    {
+                        self.pause();
+                    }
+                } else if ui
+                    .button("play")
+                    .on_hover_text("start the simulation")
+                    .clicked()
+                {
+                    self.play();
+                }
+
+                if ui
+                    .button(format!("+{} ticks", STEP_TICK_COUNT))
+                    .on_hover_text("move the particles ahead at once, without drawing the intermediate ticks")
+                    .clicked()
+                {
+                    self.broadcast(UiEvent::Step(STEP_TICK_COUNT));
+                }
+
+                if ui
+                    .button("randomize")
+                    .on_hover_text("randomly pick a new seed")
+                    .clicked()
+                {
+                    self.seed = self.random_seed();
+
+                    self.update_history();
+
+                    self.apply_seed();
+                    self.spawn();
+                }
+
+                if ui
+                    .button("reset View")
+                    .on_hover_text("reset zoom and position")
+                    .clicked()
+                {
+                    self.view = View::new();
+                }
+
+                if ui
+                    .button("reset")
+                    .on_hover_text("reset everything")
+                    .clicked()
+                {
+                    self.reset();
+                }
+
+                if ui.button("quit").on_hover_text("exit smarticles").clicked() {
+                    self.save_window_geometry();
+                    self.broadcast(UiEvent::Quit);
+                    if let Some(handle) = self.simulation_handle.take() {
+                        handle.join().unwrap();
+                    }
+                    frame.close();
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.label("seed:");
+                ui.text_edit_singleline(&mut self.seed);
+                if ui.button("apply").clicked() {
+                    self.update_history();
+
+                    self.apply_seed();
+                    self.spawn();
+                }
+                if ui
+                    .button("copy")
+                    .on_hover_text("copy seed to clipboard")
+                    .clicked()
+                {
+                    ui.output().copied_text = self.seed.to_owned();
+                    self.show_toast("seed copied to clipboard");
+                }
+                if ui
+                    .button("paste")
+                    .on_hover_text("paste seed from clipboard")
+                    .clicked()
+                {
+                    self.awaiting_paste = true;
+                    self.show_toast("press ctrl+v to paste the seed");
+                }
+            });
+
+            ui.horizontal(|ui| {
+                ui.label("spawn density:");
+                let slider = ui
+                    .add(
+                        Slider::new(&mut self.spawn_density, MIN_SPAWN_DENSITY..=MAX_SPAWN_DENSITY)
+                            .logarithmic(true),
+                    )
+                    .on_hover_text("particles per unit of area when spawning, changing it respawns the particles");
+                if slider.changed() {
+                    self.send_spawn_density();
+                }
+                // Respawning every frame of a drag would be wasteful.
+                if slider.drag_released() || (slider.changed() && !slider.dragged()) {
+                    self.spawn();
+                }
+            });
+
+            let mut spawn_shape_changed = false;
+            ui.horizontal(|ui| {
+                ui.label("spawn shape:");
+                ComboBox::from_id_source("spawn shape")
+                    .selected_text(self.spawn_shape.name())
+                    .show_ui(ui, |ui| {
+                        for spawn_shape in [
+                            SpawnShape::Disk,
+                            SpawnShape::Ring {
+                                inner_radius: DEFAULT_RING_INNER_RADIUS,
+                                outer_radius: DEFAULT_RING_OUTER_RADIUS,
+                            },
+                            SpawnShape::Grid {
+                                cols: DEFAULT_GRID_COLS,
+                            },
+                            SpawnShape::Clustered {
+                                num_clusters: self.shared.class_count,
+                            },
+                        ] {
+                            if ui
+                                .selectable_label(
+                                    self.spawn_shape.name() == spawn_shape.name(),
+                                    spawn_shape.name(),
+                                )
+                                .clicked()
+                            {
+                                self.spawn_shape = spawn_shape;
+                                spawn_shape_changed = true;
+                            }
+                        }
+                    });
+            });
+            match &mut self.spawn_shape {
+                SpawnShape::Disk => (),
+                SpawnShape::Ring {
+                    inner_radius,
+                    outer_radius,
+                } => {
+                    spawn_shape_changed |= ui
+                        .add(Slider::new(inner_radius, 0.0..=MAX_RING_RADIUS).text("inner radius"))
+                        .changed();
+                    spawn_shape_changed |= ui
+                        .add(Slider::new(outer_radius, 0.0..=MAX_RING_RADIUS).text("outer radius"))
+                        .changed();
+                }
+                SpawnShape::Grid { cols } => {
+                    spawn_shape_changed |= ui
+                        .add(Slider::new(cols, 1..=MAX_GRID_COLS).text("columns"))
+                        .on_hover_text("the spacing of the grid depends on the spawn density")
+                        .changed();
+                }
+                SpawnShape::Clustered { num_clusters } => {
+                    spawn_shape_changed |= ui
+                        .add(Slider::new(num_clusters, 1..=self.shared.class_count).text("clusters"))
+                        .changed();
+                }
+            }
+            if spawn_shape_changed {
+                self.send_spawn_shape();
+                self.spawn();
+            }
+
+            ui.horizontal(|ui| {
+                ui.label("mutation noise:");
+                ui.add(Slider::new(
+                    &mut self.mutation_noise,
+                    1..=MAX_MUTATION_NOISE,
+                ));
+                if ui
+                    .button("mutate seed")
+                    .on_hover_text("randomly change the forces of the current seed a little")
+                    .clicked()
+                {
+                    self.mutate_seed();
+                }
+            });
+
+            ui.collapsing("compare seeds", |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("seed:");
+                    ui.text_edit_singleline(&mut self.comparison_seed)
+                        .on_hover_text("leave empty to compare with a random seed");
+                });
+                ui.horizontal(|ui| {
+                    if ui
+                        .button("compare")
+                        .on_hover_text("run this seed next to the current one")
+                        .clicked()
+                    {
+                        self.start_comparison(ctx);
+                    }
+                    if ui
+                        .add_enabled(self.comparison.is_some(), Button::new("stop"))
+                        .clicked()
+                    {
+                        self.stop_comparison();
+                    }
+                    ui.checkbox(&mut self.diff_highlight, "diff highlight")
+                        .on_hover_text("highlight the particles that are not where their class is in the other simulation");
+                });
+            });
+
+            ui.collapsing("combine seeds", |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("seed a:");
+                    ui.text_edit_singleline(&mut self.seed_a);
+                });
+                ui.horizontal(|ui| {
+                    ui.label("seed b:");
+                    ui.text_edit_singleline(&mut self.seed_b);
+                });
+                ui.horizontal(|ui| {
+                    ui.label("blend factor:");
+                    ui.add(Slider::new(&mut self.blend_factor, 0.0..=1.0));
+                    if ui
+                        .button("apply")
+                        .on_hover_text("apply a mix of the two custom seeds")
+                        .clicked()
+                    {
+                        match (
+                            decode_seed(&self.seed_a),
+                            decode_seed(&self.seed_b),
+                        ) {
+                            (Some(a), Some(b)) => {
+                                self.apply_seed_bytes(blend_seeds(&a, &b, self.blend_factor))
+                            }
+                            _ => self.show_toast("both seeds must be custom seeds (@...)"),
+                        }
+                    }
+                });
+                ui.horizontal(|ui| {
+                    ui.label("crossover probability:");
+                    ui.add(Slider::new(&mut self.crossover_probability, 0.0..=1.0));
+                    if ui
+                        .button("apply")
+                        .on_hover_text(
+                            "apply a seed whose values are randomly picked from the two custom seeds",
+                        )
+                        .clicked()
+                    {
+                        match (decode_seed(&self.seed_a), decode_seed(&self.seed_b)) {
+                            (Some(a), Some(b)) => self.apply_seed_bytes(crossover_seeds(
+                                &a,
+                                &b,
+                                self.crossover_probability,
+                            )),
+                            _ => self.show_toast("both seeds must be custom seeds (@...)"),
+                        }
+                    }
+                });
+            });
+
+            ui.horizontal(|ui| {
+                ui.label("particle classes:");
+                let previous_class_count = self.shared.class_count;
+                let class_count = ui.add(Slider::new(
+                    &mut self.shared.class_count,
+                    MIN_CLASSES..=class_limit(),
+                ));
+                let reset = ui.button("reset");
+                if reset.clicked() {
+                    self.shared.class_count = class_limit();
+                }
+                if class_count.changed() || reset.clicked() {
+                    self.seed = self.export();
+
+                    // Disabled classes fade out first, the
+                    // simulation is updated once they are hidden.
+                    if self.shared.class_count < previous_class_count {
+                        self.class_count_pending = true;
+                    } else {
+                        self.class_count_pending = false;
+                        self.spawn();
+                        self.send_class_count();
+                    }
+                }
+            });
+
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut self.show_minimap, "show minimap");
+                ui.checkbox(&mut self.show_connections, "show connections")
+                    .on_hover_text("draw lines between interacting particles (slow)");
+            });
+            ui.checkbox(&mut self.color_by_interaction, "color by interaction type")
+                .on_hover_text("green when the particle is attracted by the class most of its neighbors belong to, red when it is repelled and grey when there is no force or no neighbor");
+
+            ui.horizontal(|ui| {
+                ui.label("total particle count:");
 
-                    self.apply_seed();
-                    self.spawn();
+                let total_particle_count: usize =
+                    self.shared.particle_counts[..self.shared.class_count].iter().sum();
+                ui.code(total_particle_count.to_string());
+            });
+
+            ui.collapsing("particle count chart", |ui| {
+                for c in 0..self.shared.class_count {
+                    let count = self.shared.particle_counts[c];
+                    let (rect, resp) = ui.allocate_exact_size(
+                        Vec2::new(ui.available_width(), PARTICLE_COUNT_BAR_HEIGHT),
+                        Sense::hover(),
+                    );
+                    let mut bar = rect;
+                    bar.set_width(rect.width() * count as f32 / MAX_PARTICLE_COUNT as f32);
+                    ui.painter().rect_filled(bar, 0., self.classes[c].color);
+                    resp.on_hover_text(format!("{}: {}", self.classes[c].heading, count));
                 }
+            });
+
+            ui.horizontal(|ui| {
+                ui.label("calculation time:");
+                ui.code(self.calculation_time.to_string() + "ms");
+            });
+
+            ui.horizontal(|ui| {
+                ui.label("ticks:");
+                ui.code(format_thousands(self.tick_count));
+                ui.label("sim time:");
+                ui.code(format!("{:.1} s", self.tick_count as f32 * config().dt));
+            });
 
+            ui.horizontal(|ui| {
                 if ui
-                    .button("reset View")
-                    .on_hover_text("reset zoom and position")
-                    .clicked()
+                    .checkbox(&mut self.show_kinetic_energy, "kinetic energy:")
+                    .on_hover_text("sum of m v² / 2 over all the particles, the velocities are only sent by the simulation when this is checked")
+                    .changed()
                 {
-                    self.view = View::DEFAULT;
+                    self.send_show_kinetic_energy();
+                    self.kinetic_energy = None;
+                }
+                if self.show_kinetic_energy {
+                    match self.kinetic_energy {
+                        Some(kinetic_energy) => ui.code(format!("{:.1}", kinetic_energy)),
+                        None => ui.code("-"),
+                    };
                 }
+            });
+
+            ui.collapsing("order parameter", |ui| {
+                ui.add(
+                    ProgressBar::new(self.order_parameter)
+                        .text(format!("{:.2}", self.order_parameter)),
+                )
+                .on_hover_text("1 when all the particles move in the same direction, 0 when they move randomly");
+
+                let points: PlotPoints = self
+                    .order_parameter_history
+                    .iter()
+                    .enumerate()
+                    .map(|(i, order)| [i as f64, *order as f64])
+                    .collect();
+                Plot::new("order parameter")
+                    .view_aspect(3.)
+                    .include_y(0.)
+                    .include_y(1.)
+                    .allow_drag(false)
+                    .allow_zoom(false)
+                    .show(ui, |plot_ui| plot_ui.line(Line::new(points)));
+
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut self.auto_save_ordered, "save seed above")
+                        .on_hover_text("add the seed to the history when the order parameter goes above the threshold");
+                    ui.add(Slider::new(&mut self.order_threshold, 0.0..=1.));
+                });
+            });
+
+            ui.collapsing("performance", |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("worker threads:");
+                    if ui
+                        .add(DragValue::new(&mut self.thread_count).clamp_range(1..=MAX_THREAD_COUNT))
+                        .changed()
+                    {
+                        self.send_thread_count();
+                    }
+                });
+
+                ui.horizontal(|ui| {
+                    let mut limit_fps = self.target_fps.is_some();
+                    ui.checkbox(&mut limit_fps, "target FPS:");
+                    let mut target_fps = self.target_fps.unwrap_or(DEFAULT_TARGET_FPS);
+                    ui.add_enabled(
+                        limit_fps,
+                        Slider::new(&mut target_fps, MIN_TARGET_FPS..=MAX_TARGET_FPS),
+                    );
+                    self.target_fps = if limit_fps { Some(target_fps) } else { None };
+                });
+
+                let mut times: Vec<Duration> =
+                    self.calculation_time_history.iter().copied().collect();
+                times.sort();
+                let percentile = |p: f32| {
+                    if times.is_empty() {
+                        Duration::ZERO
+                    } else {
+                        times[((times.len() - 1) as f32 * p).round() as usize]
+                    }
+                };
+                let mean = if times.is_empty() {
+                    Duration::ZERO
+                } else {
+                    times.iter().sum::<Duration>() / times.len() as u32
+                };
+
+                Grid::new("performance").striped(true).show(ui, |ui| {
+                    for (label, time) in [
+                        ("mean", mean),
+                        ("p50", percentile(0.5)),
+                        ("p95", percentile(0.95)),
+                        ("p99", percentile(0.99)),
+                        ("peak", self.peak_calculation_time),
+                    ] {
+                        ui.label(label);
+                        ui.code(format!("{:.1}ms", time.as_secs_f32() * 1000.));
+                        ui.end_row();
+                    }
+                });
 
                 if ui
                     .button("reset")
-                    .on_hover_text("reset everything")
+                    .on_hover_text("forget previous calculation times")
                     .clicked()
                 {
-                    self.reset();
+                    self.calculation_time_history.clear();
+                    self.peak_calculation_time = Duration::ZERO;
                 }
+            });
 
-                if ui.button("quit").on_hover_text("exit smarticles").clicked() {
-                    self.ui_send.send(UiEvent::Quit).unwrap();
-                    if let Some(handle) = self.simulation_handle.take() {
-                        handle.join().unwrap();
+            ui.collapsing("performance breakdown", |ui| {
+                let steps = [
+                    ("spawn", self.perf_breakdown.spawn, Color32::from_rgb(60, 80, 255)),
+                    ("forces", self.perf_breakdown.forces, Color32::from_rgb(247, 0, 243)),
+                    (
+                        "post processing",
+                        self.perf_breakdown.post_processing,
+                        Color32::from_rgb(68, 255, 0),
+                    ),
+                    ("send", self.perf_breakdown.send, Color32::from_rgb(255, 140, 0)),
+                ];
+                time_breakdown(ui, "performance breakdown", &steps);
+                ui.label("the spawn time is the one of the last spawn, the other ones are per tick");
+
+                ui.add_space(10.);
+                ui.label("forces per class:");
+                let class_steps = (0..self.shared.class_count)
+                    .map(|c| {
+                        (
+                            self.classes[c].name.as_str(),
+                            self.perf_breakdown.class_forces[c],
+                            self.classes[c].color,
+                        )
+                    })
+                    .collect::<Vec<_>>();
+                time_breakdown(ui, "class forces breakdown", &class_steps);
+            });
+
+            ui.collapsing("explore seeds", |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("seeds:");
+                    ui.add(Slider::new(&mut self.batch_seed_count, 1..=MAX_BATCH_SEED_COUNT));
+                });
+                ui.horizontal(|ui| {
+                    ui.label("ticks:");
+                    ui.add(
+                        Slider::new(
+                            &mut self.batch_tick_count,
+                            MIN_BATCH_TICK_COUNT..=MAX_BATCH_TICK_COUNT,
+                        )
+                        .logarithmic(true),
+                    )
+                    .on_hover_text("number of ticks each seed is run for before being scored");
+                });
+                ui.horizontal(|ui| {
+                    ui.label("rank by:");
+                    ComboBox::from_id_source("batch metric")
+                        .selected_text(self.batch_metric.name())
+                        .show_ui(ui, |ui| {
+                            for metric in [BatchMetric::OrderParameter, BatchMetric::MeanDistance] {
+                                ui.selectable_value(&mut self.batch_metric, metric, metric.name());
+                            }
+                        });
+                });
+                ui.horizontal(|ui| {
+                    if ui
+                        .add_enabled(self.batch_rcv.is_none(), Button::new("explore"))
+                        .on_hover_text("run random seeds in the background and rank them")
+                        .clicked()
+                    {
+                        self.explore_seeds(ctx);
                     }
-                    frame.close();
+                    if self.batch_rcv.is_some() {
+                        ui.spinner();
+                        if ui
+                            .button("stop")
+                            .on_hover_text("stop the exploration, the seeds that were fully run are still ranked")
+                            .clicked()
+                        {
+                            self.batch_stop.store(true, Ordering::Relaxed);
+                        }
+                    }
+                    if !self.batch_results.is_empty()
+                        && ui
+                            .button("export CSV")
+                            .on_hover_text("save the ranked seeds as a csv file")
+                            .clicked()
+                    {
+                        self.export_batch_csv();
+                    }
+                });
+
+                let mut selected = None;
+                Grid::new("batch results").striped(true).show(ui, |ui| {
+                    for (rank, result) in self.batch_results.iter().enumerate() {
+                        ui.label(format!("{}.", rank + 1));
+                        if ui
+                            .add(Button::new(&result.seed).small())
+                            .on_hover_text("load this seed")
+                            .clicked()
+                        {
+                            selected = Some(result.seed.to_owned());
+                        }
+                        ui.code(format!("{:.2}", result.score));
+                        ui.end_row();
+                    }
+                });
+                if let Some(seed) = selected {
+                    self.seed = seed;
+                    self.update_history();
+                    self.apply_seed();
+                    self.spawn();
                 }
             });
-            ui.horizontal(|ui| {
-                ui.label("seed:");
-                ui.text_edit_singleline(&mut self.seed);
-                if ui.button("apply").clicked() {
-                    self.update_history();
 
+            ui.collapsing("interesting patterns", |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("pattern score:");
+                    let score = match self.pattern_score {
+                        Some(score) => format!("{:.2}", score),
+                        None => "-".to_owned(),
+                    };
+                    ui.code(score).on_hover_text(format!(
+                        "entropy of the particle density after {} ticks, 0 when all the particles are in the same place and 1 when they are spread evenly",
+                        PATTERN_SCORE_TICK
+                    ));
+                });
+                ui.horizontal(|ui| {
+                    ui.label("flag below:");
+                    ui.add(Slider::new(&mut self.pattern_threshold, 0.0..=1.));
+                });
+
+                let mut selected = None;
+                for (i, (seed, score)) in self.flagged_seeds.iter().enumerate() {
+                    ui.horizontal(|ui| {
+                        ui.code(format!("{:.2}", score));
+                        if ui
+                            .add(Button::new(seed).small())
+                            .on_hover_text("load this seed")
+                            .clicked()
+                        {
+                            selected = Some(i);
+                        }
+                    });
+                }
+                if let Some(i) = selected {
+                    self.seed = self.flagged_seeds[i].0.to_owned();
                     self.apply_seed();
                     self.spawn();
                 }
+                if !self.flagged_seeds.is_empty() && ui.button("clear").clicked() {
+                    self.flagged_seeds.clear();
+                }
             });
 
-            ui.horizontal(|ui| {
-                ui.label("particle classes:");
-                let class_count = ui.add(Slider::new(
-                    &mut self.shared.class_count,
-                    MIN_CLASSES..=MAX_CLASSES,
-                ));
-                let reset = ui.button("reset");
-                if reset.clicked() {
-                    self.shared.class_count = MAX_CLASSES;
+            if self.history.len() > 1 {
+                ui.collapsing("seed history", |ui| {
+                    self.cache_history_previews();
+                    let mut selected = None;
+                    ComboBox::from_id_source("seed history")
+                        .width(200.)
+                        .selected_text(self.history[self.selected_history_entry].to_owned())
+                        .show_ui(ui, |ui| {
+                            for (i, seed) in
+                                self.history.iter().enumerate().take(MAX_HISTORY_LEN)
+                            {
+                                ui.horizontal(|ui| {
+                                    force_preview(ui, &self.history_previews[seed]);
+                                    if ui
+                                        .selectable_label(i == self.selected_history_entry, seed)
+                                        .clicked()
+                                    {
+                                        selected = Some(i);
+                                    }
+                                });
+                            }
+                        });
+                    if let Some(i) = selected {
+                        self.selected_history_entry = i;
+                        self.seed = self.history[i].to_owned();
+                        self.apply_seed();
+                        self.spawn();
+                    }
+                    if ui
+                        .button("clear history")
+                        .on_hover_text("remove all the seeds from the history")
+                        .clicked()
+                    {
+                        self.history.clear();
+                        self.history_previews.clear();
+                        self.selected_history_entry = 0;
+                        storage::clear_history();
+                    }
+                });
+            }
+
+            ui.collapsing("presets", |ui| {
+                if !self.presets.is_empty() {
+                    ui.horizontal(|ui| {
+                        ComboBox::from_id_source("presets").width(150.).show_index(
+                            ui,
+                            &mut self.selected_preset,
+                            self.presets.len(),
+                            |i| self.presets[i].name.to_owned(),
+                        );
+                        if ui
+                            .button("load")
+                            .on_hover_text("load the selected preset")
+                            .clicked()
+                        {
+                            self.load_preset();
+                        }
+                        if ui
+                            .button("delete")
+                            .on_hover_text("delete the selected preset")
+                            .clicked()
+                        {
+                            self.delete_preset();
+                        }
+                    });
                 }
-                if class_count.changed() || reset.clicked() {
-                    self.seed = self.export();
-                    self.spawn();
+                ui.horizontal(|ui| {
+                    ui.label("name:");
+                    ui.text_edit_singleline(&mut self.preset_name);
+                    if ui
+                        .add_enabled(
+                            !self.preset_name.trim().is_empty(),
+                            egui::Button::new("save preset"),
+                        )
+                        .on_hover_text("save the current configuration under this name")
+                        .clicked()
+                    {
+                        self.save_preset();
+                    }
+                });
+            });
+
+            ui.collapsing("recording", |ui| {
+                ui.horizontal(|ui| {
+                    if self.is_recording {
+                        if ui.button("stop recording").clicked() {
+                            self.is_recording = false;
+                        }
+                    } else if ui.button("start recording").clicked() {
+                        self.start_recording();
+                    }
+                    if let Some(recording) = &self.recording {
+                        ui.label(format!("{} frames", recording.len()));
+                    }
+                });
+
+                let has_frames = self.recording.as_ref().map_or(false, |r| !r.is_empty());
+                ui.horizontal(|ui| {
+                    if self.playback_frame.is_some() {
+                        if ui
+                            .button(if self.playback_playing { "pause" } else { "play" })
+                            .clicked()
+                        {
+                            self.playback_playing = !self.playback_playing;
+                        }
+                        if ui.button("stop playback").clicked() {
+                            self.stop_playback();
+                        }
+                    } else if ui
+                        .add_enabled(has_frames, Button::new("play back"))
+                        .clicked()
+                    {
+                        self.start_playback();
+                    }
+                });
+
+                if let (Some(frame), Some(recording)) = (self.playback_frame, &self.recording) {
+                    let mut frame = frame;
+                    if ui
+                        .add(Slider::new(&mut frame, 0..=recording.len() - 1).text("frame"))
+                        .changed()
+                    {
+                        recording.copy_frame(frame, &mut self.particle_positions);
+                        self.playback_frame = Some(frame);
+                    }
+                }
+
+                ui.horizontal(|ui| {
+                    if ui
+                        .add_enabled(has_frames, Button::new("save recording"))
+                        .clicked()
+                    {
+                        self.save_recording();
+                    }
+                    if ui.button("load recording").clicked() {
+                        self.load_recording();
+                    }
+                });
+
+                ui.horizontal(|ui| {
+                    if ui
+                        .button("save state")
+                        .on_hover_text("save the positions, velocities and settings as JSON")
+                        .clicked()
+                    {
+                        self.save_state();
+                    }
+                    if ui.button("load state").clicked() {
+                        self.load_state();
+                    }
+                });
 
-                    self.send_class_count();
+                if ui
+                    .button("export SVG")
+                    .on_hover_text("save the current frame as a vector image")
+                    .clicked()
+                {
+                    self.export_svg();
                 }
             });
 
-            ui.horizontal(|ui| {
-                ui.label("total particle count:");
+            ui.collapsing("focus", |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("class:");
+                    ComboBox::from_id_source("class").show_index(
+                        ui,
+                        &mut self.selected_particle.0,
+                        self.classes.len(),
+                        |i| self.classes[i].heading.to_owned(),
+                    );
+                    self.clamp_selected_particle();
+                    match self.shared.particle_counts[self.selected_particle.0] {
+                        0 => {
+                            ui.label("no particle in this class");
+                        }
+                        count => {
+                            ui.label("particle index:");
+                            ui.add(Slider::new(&mut self.selected_particle.1, 0..=count - 1));
+                        }
+                    }
+                });
 
-                let total_particle_count: usize = self.shared.particle_counts.iter().sum();
-                ui.code(total_particle_count.to_string());
+                // Follow the new selection if it changed.
+                self.follow_mode = match self.follow_mode {
+                    FollowMode::Particle(..) => {
+                        FollowMode::Particle(self.selected_particle.0, self.selected_particle.1)
+                    }
+                    FollowMode::ClassCentroid(_) => {
+                        FollowMode::ClassCentroid(self.selected_particle.0)
+                    }
+                    follow_mode => follow_mode,
+                };
+
+                ui.horizontal(|ui| {
+                    ui.label("position:");
+                    ui.code(format!(
+                        "{:?}",
+                        self.particle_positions[self.selected_particle]
+                    ));
+                });
+
+                // Distance to the centroid of each class, the closest
+                // one is marked.
+                let particle_pos = self.particle_positions[self.selected_particle];
+                let distances: Vec<(usize, f32)> = (0..self.shared.class_count)
+                    .filter_map(|c| {
+                        self.class_centroid(c)
+                            .map(|centroid| (c, (centroid - particle_pos).length()))
+                    })
+                    .collect();
+                let closest_class = distances
+                    .iter()
+                    .min_by(|a, b| a.1.total_cmp(&b.1))
+                    .map(|(c, _)| *c);
+                Grid::new("centroid distances").striped(true).show(ui, |ui| {
+                    for (c, distance) in &distances {
+                        ui.colored_label(self.classes[*c].color, &self.classes[*c].heading);
+                        ui.code(format!("{:.1}", distance));
+                        if Some(*c) == closest_class {
+                            ui.label("closest");
+                        }
+                        ui.end_row();
+                    }
+                    if let Some(centroid) = self.global_centroid() {
+                        ui.label("global centroid");
+                        ui.code(format!("{:.1}", (centroid - particle_pos).length()));
+                        ui.end_row();
+                    }
+                });
+
+                ui.collapsing("nearby particles", |ui| {
+                    self.nearby_particles(ui);
+                });
+
+                ui.horizontal(|ui| {
+                    ui.label("follow:");
+                    let mut follow_mode = self.follow_mode;
+                    ui.selectable_value(&mut follow_mode, FollowMode::None, "nothing");
+                    ui.selectable_value(
+                        &mut follow_mode,
+                        FollowMode::Particle(self.selected_particle.0, self.selected_particle.1),
+                        "selected particle",
+                    );
+                    ui.selectable_value(
+                        &mut follow_mode,
+                        FollowMode::ClassCentroid(self.selected_particle.0),
+                        "class centroid",
+                    );
+                    ui.selectable_value(
+                        &mut follow_mode,
+                        FollowMode::GlobalCentroid,
+                        "global centroid",
+                    );
+                    if follow_mode != self.follow_mode {
+                        self.set_follow_mode(follow_mode);
+                    }
+                });
+
+                if let FollowMode::ClassCentroid(_) | FollowMode::GlobalCentroid = self.follow_mode
+                {
+                    ui.horizontal(|ui| {
+                        ui.label("centroid position:");
+                        match self.follow_target() {
+                            Some(centroid) => ui.code(format!("{:?}", centroid)),
+                            None => ui.code("none"),
+                        };
+                    });
+                }
             });
 
-            ui.horizontal(|ui| {
-                ui.label("calculation time:");
-                ui.code(self.calculation_time.to_string() + "ms");
+            ui.collapsing("force field", |ui| {
+                ui.checkbox(&mut self.show_force_field, "show force field");
+                ui.horizontal(|ui| {
+                    ui.label("reference class:");
+                    ComboBox::from_id_source("force field class").show_index(
+                        ui,
+                        &mut self.force_field_class,
+                        self.shared.class_count,
+                        |i| self.classes[i].heading.to_owned(),
+                    );
+                });
             });
 
-            if self.history.len() > 1 {
-                ui.collapsing("seed history", |ui| {
-                    if ComboBox::from_id_source("seed history")
-                        .width(200.)
-                        .show_index(
-                            ui,
-                            &mut self.selected_history_entry,
-                            self.history.len(),
-                            |i| self.history[i].to_owned(),
+            ui.collapsing("external forces", |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("temperature:");
+                    if ui
+                        .add(Slider::new(&mut self.temperature, 0.0..=MAX_TEMPERATURE))
+                        .on_hover_text("strength of the random noise added to the particle velocities")
+                        .changed()
+                    {
+                        self.send_temperature();
+                    }
+                });
+                ui.horizontal(|ui| {
+                    ui.label("close force:");
+                    if ui
+                        .add(
+                            Slider::new(&mut self.close_force, MIN_CLOSE_FORCE..=MAX_CLOSE_FORCE)
+                                .logarithmic(true),
+                        )
+                        .on_hover_text("repulsion between very close particles, high values pack them tightly and low values let them overlap")
+                        .changed()
+                    {
+                        self.send_close_force();
+                    }
+                });
+                ui.horizontal(|ui| {
+                    ui.label("gravity:");
+                    let angle = ui
+                        .add(
+                            Slider::new(&mut self.gravity_angle, 0.0..=360.)
+                                .suffix("°")
+                                .clamp_to_range(true),
                         )
+                        .on_hover_text("direction of the gravity, 90° is down");
+                    let magnitude = ui
+                        .add(Slider::new(&mut self.gravity_magnitude, 0.0..=MAX_GRAVITY))
+                        .on_hover_text("strength of the gravity");
+                    if angle.changed() || magnitude.changed() {
+                        self.send_gravity();
+                    }
+                });
+                ui.horizontal(|ui| {
+                    ui.label("vortex:");
+                    if ui
+                        .add(Slider::new(
+                            &mut self.vortex_strength,
+                            -MAX_VORTEX_STRENGTH..=MAX_VORTEX_STRENGTH,
+                        ))
+                        .on_hover_text("rotation around the origin, negative is clockwise")
                         .changed()
                     {
-                        self.seed = self.history[self.selected_history_entry].to_owned();
-                        self.apply_seed();
-                        self.spawn();
-                    };
+                        self.send_vortex_strength();
+                    }
                 });
-            }
-
-            ui.collapsing("particle inspector", |ui| {
                 ui.horizontal(|ui| {
-                    ui.label("class:");
-                    ComboBox::from_id_source("class").show_index(
-                        ui,
-                        &mut self.selected_particle.0,
-                        self.classes.len(),
-                        |i| self.classes[i].heading.to_owned(),
-                    );
-                    ui.label("particle index:");
+                    ui.label("attractor strength:");
                     ui.add(Slider::new(
-                        &mut self.selected_particle.1,
-                        0..=(self.shared.particle_counts[self.selected_particle.0] - 1),
-                    ));
+                        &mut self.attractor.strength,
+                        0.0..=MAX_ATTRACTOR_STRENGTH,
+                    ))
+                    .on_hover_text(
+                        "hold ctrl over the canvas to attract the particles, ctrl+shift to repel them",
+                    );
                 });
+            });
 
+            ui.collapsing("confinement", |ui| {
+                let mut changed = false;
                 ui.horizontal(|ui| {
-                    ui.label("position:");
-                    ui.code(format!(
-                        "{:?}",
-                        self.particle_positions[self.selected_particle]
-                    ));
+                    ui.label("shape:");
+                    ComboBox::from_id_source("confinement")
+                        .selected_text(self.boundary.name())
+                        .show_ui(ui, |ui| {
+                            for boundary in [
+                                BoundaryShape::None,
+                                BoundaryShape::Circle(DEFAULT_BOUNDARY_SIZE / 2.),
+                                BoundaryShape::Rectangle(DEFAULT_BOUNDARY_SIZE, DEFAULT_BOUNDARY_SIZE),
+                                BoundaryShape::Ellipse(DEFAULT_BOUNDARY_SIZE, DEFAULT_BOUNDARY_SIZE),
+                            ] {
+                                if ui
+                                    .selectable_label(
+                                        self.boundary.name() == boundary.name(),
+                                        boundary.name(),
+                                    )
+                                    .clicked()
+                                {
+                                    self.boundary = boundary;
+                                    changed = true;
+                                }
+                            }
+                        });
                 });
+                match &mut self.boundary {
+                    BoundaryShape::None => (),
+                    BoundaryShape::Circle(radius) => {
+                        changed |= ui
+                            .add(
+                                Slider::new(radius, MIN_BOUNDARY_SIZE / 2.0..=MAX_BOUNDARY_SIZE / 2.)
+                                    .text("radius"),
+                            )
+                            .changed();
+                    }
+                    BoundaryShape::Rectangle(width, height)
+                    | BoundaryShape::Ellipse(width, height) => {
+                        changed |= ui
+                            .add(Slider::new(width, MIN_BOUNDARY_SIZE..=MAX_BOUNDARY_SIZE).text("width"))
+                            .changed();
+                        changed |= ui
+                            .add(Slider::new(height, MIN_BOUNDARY_SIZE..=MAX_BOUNDARY_SIZE).text("height"))
+                            .changed();
+                    }
+                }
+                changed |= ui
+                    .add(Slider::new(&mut self.restitution, 0.0..=1.).text("restitution"))
+                    .on_hover_text("fraction of the velocity kept when bouncing on the boundary")
+                    .changed();
+                if changed {
+                    self.send_boundary();
+                }
 
                 ui.horizontal(|ui| {
-                    if self.follow_selected_particle {
-                        if ui.button("stop following selected particle").clicked() {
-                            self.view.pos -= self.particle_positions[self.selected_particle];
-                            self.follow_selected_particle = false;
-                        }
-                    } else if ui.button("focus and follow selected particle").clicked() {
-                        self.view.pos *= 0.;
-                        self.follow_selected_particle = true;
+                    let mut limited = self.max_particles_per_cell.is_some();
+                    let checkbox = ui
+                        .checkbox(&mut limited, "max density:")
+                        .on_hover_text("max number of particles in a small square, the excess particles are moved to the center");
+                    let mut max_particles_per_cell = self
+                        .max_particles_per_cell
+                        .unwrap_or(DEFAULT_MAX_PARTICLES_PER_CELL);
+                    let slider = ui.add_enabled(
+                        limited,
+                        Slider::new(&mut max_particles_per_cell, 1..=MAX_PARTICLES_PER_CELL),
+                    );
+                    if checkbox.changed() || slider.changed() {
+                        self.max_particles_per_cell = limited.then_some(max_particles_per_cell);
+                        self.send_max_particles_per_cell();
                     }
                 });
             });
 
+            ui.collapsing("basin explorer", |ui| {
+                ui.label("runs the current seed several times with different spawns and overlays them, to see if it always ends up in the same state");
+                let mut enabled = self.basin_buffer.is_some();
+                let checkbox = ui.checkbox(&mut enabled, "enabled");
+                let slider = ui
+                    .add(Slider::new(&mut self.basin_runs, MIN_BASIN_RUNS..=MAX_BASIN_RUNS).text("runs"))
+                    .on_hover_text("number of runs, including the main one");
+                if checkbox.changed() || (enabled && slider.changed()) {
+                    self.send_basin_explorer(enabled);
+                }
+            });
+
+            ui.collapsing("interaction graph", |ui| {
+                ui.checkbox(&mut self.show_interaction_graph, "show interaction graph");
+                if self.show_interaction_graph {
+                    self.interaction_graph(ui);
+                }
+            });
+
+            ui.collapsing("interaction frequency", |ui| {
+                ui.label("number of particle pairs within the action radius during the last tick");
+                self.interaction_heatmap(ui);
+            });
+
+            ui.collapsing("force matrix animation", |ui| {
+                ui.label("force of the column class on the row class, changes fade in");
+                self.force_heatmap(ui);
+            });
+
+            ui.collapsing("adjacency", |ui| {
+                ui.label("number of particle pairs that are touching during the last tick");
+                if ui
+                    .checkbox(&mut self.count_adjacencies, "count adjacent pairs")
+                    .on_hover_text("the simulation only counts them when this is checked")
+                    .changed()
+                {
+                    self.send_count_adjacencies();
+                }
+                if self.count_adjacencies {
+                    self.adjacency_heatmap(ui);
+                    if ui
+                        .button("export CSV")
+                        .on_hover_text("save the adjacency matrix as a csv file")
+                        .clicked()
+                    {
+                        self.export_adjacency_csv();
+                    }
+                }
+            });
+
+            ui.collapsing("force asymmetry", |ui| {
+                ui.label("force of the column class on the row class minus the opposite force, the diagonal is always balanced");
+                self.asymmetry_heatmap(ui);
+            });
+
             ui.collapsing(
                 "velocity elementary variation with respect to distance between particles",
                 |ui| {
-                    let points: PlotPoints = (0..1000)
+                    let points: PlotPoints = (0..2000)
                         .map(|i| {
                             let x = i as f32 * 0.1;
                             [
@@ -453,6 +3581,10 @@ impl App for Smarticles {
                                     self.shared.param_matrix[self.selected_param].radius,
                                     self.shared.param_matrix[self.selected_param].force
                                         * FORCE_FACTOR,
+                                    self.shared.param_matrix[self.selected_param]
+                                        .long_range_force
+                                        * FORCE_FACTOR,
+                                    self.close_force * FORCE_FACTOR,
                                 )
                                 .x as f64,
                             ]
@@ -465,21 +3597,110 @@ impl App for Smarticles {
                 },
             );
 
+            if ui
+                .checkbox(&mut self.symmetric_mode, "symmetric forces")
+                .on_hover_text("the force of class a on class b is the same as the force of class b on class a")
+                .changed()
+                && self.symmetric_mode
+            {
+                self.symmetrize_forces();
+            }
+
+            ui.horizontal(|ui| {
+                if ui
+                    .add_enabled(!self.undo_stack.is_empty(), Button::new("undo"))
+                    .on_hover_text("undo the last param or particle count change (ctrl+z)")
+                    .clicked()
+                {
+                    self.undo();
+                }
+                if ui
+                    .add_enabled(!self.redo_stack.is_empty(), Button::new("redo"))
+                    .on_hover_text("redo the last undone change (ctrl+shift+z)")
+                    .clicked()
+                {
+                    self.redo();
+                }
+            });
+
+            ui.horizontal(|ui| {
+                let mut show_delta = self.force_baseline.is_some();
+                if ui
+                    .checkbox(&mut show_delta, "show matrix delta")
+                    .on_hover_text("show how much each force moved since this was enabled")
+                    .changed()
+                {
+                    self.force_baseline = show_delta.then(|| self.force_snapshot());
+                }
+                if show_delta && ui.button("reset baseline").clicked() {
+                    self.force_baseline = Some(self.force_snapshot());
+                }
+            });
+
+            ui.horizontal(|ui| {
+                if ui
+                    .button("export matrix CSV")
+                    .on_hover_text("save the forces as a csv file")
+                    .clicked()
+                {
+                    self.export_forces_csv();
+                }
+                if ui
+                    .button("import matrix CSV")
+                    .on_hover_text("load the forces from a csv file")
+                    .clicked()
+                {
+                    self.import_forces_csv();
+                }
+            });
+
+            // State before this frame's slider changes, saved as an
+            // undo step when a slider starts being changed.
+            let undo_entry = self.undo_entry();
+            let force_delta = self
+                .force_baseline
+                .as_ref()
+                .map(|baseline| self.force_delta(baseline));
+
             ScrollArea::vertical().show(ui, |ui| {
                 for i in 0..self.shared.class_count {
                     ui.add_space(10.);
-                    ui.colored_label(self.classes[i].color, &self.classes[i].heading);
+                    ui.horizontal(|ui| {
+                        color_picker::color_edit_button_srgba(
+                            ui,
+                            &mut self.classes[i].color,
+                            color_picker::Alpha::Opaque,
+                        );
+                        ui.label(&self.classes[i].heading);
+                        let resp = ui.add(
+                            TextEdit::singleline(&mut self.class_name_edits[i])
+                                .desired_width(80.),
+                        );
+                        if resp.lost_focus() {
+                            let name = self.class_name_edits[i].to_owned();
+                            self.rename_class(i, name);
+                        }
+                        if ui
+                            .button(if self.frozen_classes[i] { "unfreeze" } else { "freeze" })
+                            .on_hover_text("pin the particles of this class in place, they still act on the others")
+                            .clicked()
+                        {
+                            self.frozen_classes[i] = !self.frozen_classes[i];
+                            self.send_frozen_class(i);
+                        }
+                    });
                     ui.separator();
 
                     ui.horizontal(|ui| {
                         ui.label("particle count:");
-                        if ui
-                            .add(Slider::new(
-                                &mut self.shared.particle_counts[i],
-                                MIN_PARTICLE_COUNT..=MAX_PARTICLE_COUNT,
-                            ))
-                            .changed()
-                        {
+                        let slider = ui.add(Slider::new(
+                            &mut self.shared.particle_counts[i],
+                            MIN_PARTICLE_COUNT..=MAX_PARTICLE_COUNT,
+                        ));
+                        if starts_change(&slider) {
+                            self.push_undo(undo_entry.to_owned());
+                        }
+                        if slider.changed() {
                             self.seed = self.export();
                             self.spawn();
 
@@ -487,58 +3708,156 @@ impl App for Smarticles {
                         }
                     });
 
-                    ui.collapsing(self.classes[i].heading.to_owned() + " params", |ui| {
-                        ui.horizontal(|ui| {
-                            ui.vertical(|ui| {
-                                for j in 0..self.shared.class_count {
-                                    ui.horizontal(|ui| {
-                                        ui.label("force (");
-                                        ui.colored_label(
-                                            self.classes[j].color,
-                                            &self.classes[j].name,
-                                        );
-                                        ui.label(")");
-                                        if ui
-                                            .add(Slider::new(
+                    ui.horizontal(|ui| {
+                        ui.label("mass:");
+                        if ui
+                            .add(
+                                Slider::new(&mut self.shared.class_masses[i], MIN_MASS..=MAX_MASS)
+                                    .logarithmic(true),
+                            )
+                            .on_hover_text("heavier particles react more slowly to forces")
+                            .changed()
+                        {
+                            self.send_class_masses();
+                        }
+                    });
+
+                    ui.horizontal(|ui| {
+                        ui.label("birth rate:");
+                        let birth_rate = ui
+                            .add(Slider::new(
+                                &mut self.shared.birth_rates[i],
+                                0.0..=MAX_POPULATION_RATE,
+                            ))
+                            .on_hover_text("probability for each particle to give birth every tick");
+                        ui.label("death rate:");
+                        let death_rate = ui
+                            .add(Slider::new(
+                                &mut self.shared.death_rates[i],
+                                0.0..=MAX_POPULATION_RATE,
+                            ))
+                            .on_hover_text("probability for each particle to die every tick");
+                        if birth_rate.changed() || death_rate.changed() {
+                            self.send_population_rates();
+                        }
+                    });
+
+                    let scroll_to_class = matches!(self.scroll_to_param, Some((c, _)) if c == i);
+                    CollapsingHeader::new(self.classes[i].heading.to_owned() + " params")
+                        .id_source(("class params", i))
+                        .open(if scroll_to_class { Some(true) } else { None })
+                        .show(ui, |ui| {
+                            ui.horizontal(|ui| {
+                                ui.vertical(|ui| {
+                                    for j in 0..self.shared.class_count {
+                                        // (j, i) is the same slider as (i, j).
+                                        if self.symmetric_mode && j < i {
+                                            continue;
+                                        }
+                                        ui.horizontal(|ui| {
+                                            ui.label("force (");
+                                            ui.colored_label(
+                                                self.classes[j].color,
+                                                &self.classes[j].name,
+                                            );
+                                            ui.label(")");
+                                            let slider = ui.add(Slider::new(
                                                 &mut self.shared.param_matrix[(i, j)].force,
                                                 MIN_FORCE..=MAX_FORCE,
-                                            ))
-                                            .changed()
-                                        {
-                                            self.selected_param = (i, j);
-                                            self.seed = self.export();
+                                            ));
+                                            if self.scroll_to_param == Some((i, j)) {
+                                                slider.scroll_to_me(Some(Align::Center));
+                                                self.scroll_to_param = None;
+                                            }
+                                            if starts_change(&slider) {
+                                                self.push_undo(undo_entry.to_owned());
+                                            }
+                                            if slider.changed() {
+                                                if self.symmetric_mode {
+                                                    self.shared.param_matrix[(j, i)].force =
+                                                        self.shared.param_matrix[(i, j)].force;
+                                                }
+                                                self.selected_param = (i, j);
+                                                self.seed = self.export();
 
-                                            self.send_params();
-                                        }
-                                    });
-                                }
-                            });
-                            ui.vertical(|ui| {
-                                for j in 0..self.shared.class_count {
-                                    ui.horizontal(|ui| {
-                                        ui.label("radius (");
-                                        ui.colored_label(
-                                            self.classes[j].color,
-                                            &self.classes[j].name,
-                                        );
-                                        ui.label(")");
-                                        if ui
-                                            .add(Slider::new(
+                                                self.send_params();
+                                            }
+                                            if let Some(force_delta) = &force_delta {
+                                                let delta = force_delta[(i, j)];
+                                                if delta != 0. {
+                                                    ui.colored_label(
+                                                        force_color(delta, delta.abs()),
+                                                        format!("{:+.1}", delta),
+                                                    );
+                                                }
+                                            }
+                                        });
+                                    }
+                                });
+                                ui.vertical(|ui| {
+                                    for j in 0..self.shared.class_count {
+                                        ui.horizontal(|ui| {
+                                            ui.label("radius (");
+                                            ui.colored_label(
+                                                self.classes[j].color,
+                                                &self.classes[j].name,
+                                            );
+                                            ui.label(")");
+                                            let slider = ui.add(Slider::new(
                                                 &mut self.shared.param_matrix[(i, j)].radius,
                                                 MIN_RADIUS..=MAX_RADIUS,
-                                            ))
-                                            .changed()
-                                        {
-                                            self.selected_param = (i, j);
-                                            self.seed = self.export();
+                                            ));
+                                            if starts_change(&slider) {
+                                                self.push_undo(undo_entry.to_owned());
+                                            }
+                                            if slider.changed() {
+                                                self.selected_param = (i, j);
+                                                self.seed = self.export();
 
-                                            self.send_params();
+                                                self.send_params();
+                                            }
+                                        });
+                                    }
+                                });
+                                ui.vertical(|ui| {
+                                    for j in 0..self.shared.class_count {
+                                        if self.symmetric_mode && j < i {
+                                            continue;
                                         }
-                                    });
-                                }
+                                        ui.horizontal(|ui| {
+                                            ui.label("long range (");
+                                            ui.colored_label(
+                                                self.classes[j].color,
+                                                &self.classes[j].name,
+                                            );
+                                            ui.label(")");
+                                            let slider = ui
+                                                .add(Slider::new(
+                                                    &mut self.shared.param_matrix[(i, j)]
+                                                        .long_range_force,
+                                                    MIN_LONG_RANGE_FORCE..=MAX_LONG_RANGE_FORCE,
+                                                ))
+                                                .on_hover_text("weak force applied past the radius");
+                                            if starts_change(&slider) {
+                                                self.push_undo(undo_entry.to_owned());
+                                            }
+                                            if slider.changed() {
+                                                if self.symmetric_mode {
+                                                    self.shared.param_matrix[(j, i)]
+                                                        .long_range_force = self.shared.param_matrix
+                                                        [(i, j)]
+                                                        .long_range_force;
+                                                }
+                                                self.selected_param = (i, j);
+                                                self.seed = self.export();
+
+                                                self.send_params();
+                                            }
+                                        });
+                                    }
+                                });
                             });
                         });
-                    });
                 }
             });
         });
@@ -550,7 +3869,20 @@ impl App for Smarticles {
             })
             .show(ctx, |ui| {
                 let (resp, paint) =
-                    ui.allocate_painter(ui.available_size_before_wrap(), Sense::hover());
+                    ui.allocate_painter(ui.available_size_before_wrap(), Sense::click());
+
+                // When comparing, the left half shows the simulation
+                // and the right half the comparison, with the same
+                // view.
+                let canvas_rect = match self.comparison {
+                    Some(_) => Rect::from_min_max(
+                        resp.rect.min,
+                        pos2(resp.rect.center().x, resp.rect.max.y),
+                    ),
+                    None => resp.rect,
+                };
+                let paint = paint.with_clip_rect(canvas_rect);
+                self.canvas_size = canvas_rect.size();
 
                 if resp
                     .rect
@@ -566,8 +3898,17 @@ impl App for Smarticles {
                 // This is weird but look at the values.
                 self.view.zoom = self.view.zoom.max(MIN_ZOOM).min(MAX_ZOOM);
 
-                if let Some(interact_pos) = ctx.input().pointer.interact_pos() {
-                    if ctx.input().pointer.any_down() && resp.rect.contains(interact_pos) {
+                // Overlays (like the minimap) are not part of the
+                // canvas, dragging them should not move the view.
+                let interact_pos = ctx.input().pointer.interact_pos();
+                let pointer_over_canvas =
+                    interact_pos.and_then(|pos| ctx.layer_id_at(pos)) == Some(resp.layer_id);
+
+                if let Some(interact_pos) = interact_pos {
+                    if ctx.input().pointer.any_down()
+                        && resp.rect.contains(interact_pos)
+                        && (pointer_over_canvas || self.view.dragging)
+                    {
                         if !self.view.dragging {
                             self.view.dragging = true;
                             self.view.drag_start_pos = interact_pos.to_vec2();
@@ -585,19 +3926,126 @@ impl App for Smarticles {
                         self.view.drag_start_view_pos + drag_delta.to_vec2() / self.view.zoom;
                 }
 
-                let center = resp.rect.center()
-                    + if self.follow_selected_particle {
-                        self.view.pos - self.particle_positions[self.selected_particle]
-                    } else {
-                        self.view.pos
-                    } * self.view.zoom;
+                let center = canvas_rect.center()
+                    + (self.view.pos - self.follow_target().unwrap_or(Vec2::ZERO)) * self.view.zoom;
 
-                for c in 0..self.shared.class_count {
-                    let class = &self.classes[c];
+                let modifiers = ctx.input().modifiers;
+                match resp.hover_pos() {
+                    Some(hover_pos) if modifiers.ctrl => {
+                        self.attractor_pinned = false;
+                        self.attractor.active = true;
+                        self.attractor.position = (hover_pos - center) / self.view.zoom;
+                        self.attractor.is_repulsor = modifiers.shift;
+                        self.ui_send
+                            .send(UiEvent::SetAttractor(self.attractor))
+                            .unwrap();
+
+                        paint.circle_stroke(
+                            hover_pos,
+                            MIN_RADIUS * self.view.zoom,
+                            Stroke::new(1., Color32::from_gray(200)),
+                        );
+                    }
+                    _ if self.attractor_pinned => {
+                        paint.circle_stroke(
+                            center + self.attractor.position * self.view.zoom,
+                            MIN_RADIUS * self.view.zoom,
+                            Stroke::new(1., Color32::from_gray(200)),
+                        );
+                    }
+                    _ if self.attractor.active => {
+                        self.attractor.active = false;
+                        self.ui_send
+                            .send(UiEvent::SetAttractor(self.attractor))
+                            .unwrap();
+                    }
+                    _ => (),
+                }
+
+                if resp.secondary_clicked() {
+                    if let Some(pos) = resp.interact_pointer_pos() {
+                        self.context_menu_pos = (pos - center) / self.view.zoom;
+                    }
+                }
+                let resp = resp.context_menu(|ui| self.canvas_context_menu(ui));
+
+                if self.show_connections {
+                    self.draw_connections(&paint, center);
+                }
+
+                // Every run is drawn with the same opacity so that
+                // the places where they agree stand out.
+                let run_opacity = match &self.basin_buffer {
+                    Some(_) => 1. / self.basin_runs as f32,
+                    None => 1.,
+                };
+                if let Some(basin_buffer) = &self.basin_buffer {
+                    for (positions, particle_counts) in basin_buffer.read().unwrap().iter() {
+                        for c in 0..self.shared.class_count {
+                            let class = &self.classes[c];
+                            let color = Color32::from_rgba_unmultiplied(
+                                class.color.r(),
+                                class.color.g(),
+                                class.color.b(),
+                                (class.color.a() as f32 * run_opacity) as u8,
+                            );
+                            for p in 0..particle_counts[c] {
+                                let pos = center + positions[(c, p)] * self.view.zoom;
+                                if paint.clip_rect().contains(pos) {
+                                    paint.circle_filled(pos, PARTICLE_DIAMETER, color);
+                                }
+                            }
+                        }
+                    }
+                }
+
+                let diff_references = match &self.comparison {
+                    Some(comparison) if self.diff_highlight => Some((
+                        class_references(&self.shared, &self.particle_positions),
+                        class_references(&comparison.shared, &comparison.particle_positions),
+                    )),
+                    _ => None,
+                };
+
+                let interaction_colors =
+                    self.color_by_interaction.then(|| self.interaction_colors());
+
+                for c in 0..MAX_CLASSES {
+                    let opacity = self.disabled_opacity[c] * run_opacity;
+                    if opacity == 0. {
+                        continue;
+                    }
+                    let with_opacity = |color: Color32| {
+                        Color32::from_rgba_unmultiplied(
+                            color.r(),
+                            color.g(),
+                            color.b(),
+                            (color.a() as f32 * opacity) as u8,
+                        )
+                    };
+                    let color = with_opacity(self.classes[c].color);
 
                     for p in 0..self.shared.particle_counts[c] {
                         let pos = center + self.particle_positions[(c, p)] * self.view.zoom;
                         if paint.clip_rect().contains(pos) {
+                            let color = match &interaction_colors {
+                                Some(colors) if c < self.shared.class_count => {
+                                    with_opacity(colors[c][p])
+                                }
+                                _ => color,
+                            };
+                            let color = match &diff_references {
+                                Some((_, references))
+                                    if c < self.shared.class_count
+                                        && is_unmatched(
+                                            references[c],
+                                            self.particle_positions[(c, p)],
+                                        ) =>
+                                {
+                                    DIFF_HIGHLIGHT_COLOR
+                                }
+                                _ => color,
+                            };
                             paint.circle_filled(
                                 pos,
                                 if (c, p) == self.selected_particle {
@@ -605,12 +4053,150 @@ impl App for Smarticles {
                                 } else {
                                     PARTICLE_DIAMETER
                                 },
-                                class.color,
+                                color,
+                            );
+                        }
+                    }
+                }
+
+                let boundary_stroke = Stroke::new(1., Color32::from_gray(80));
+                match self.boundary {
+                    BoundaryShape::None => (),
+                    BoundaryShape::Circle(radius) => {
+                        paint.circle_stroke(center, radius * self.view.zoom, boundary_stroke);
+                    }
+                    BoundaryShape::Rectangle(width, height) => {
+                        paint.rect_stroke(
+                            Rect::from_center_size(
+                                center,
+                                Vec2::new(width, height) * self.view.zoom,
+                            ),
+                            0.,
+                            boundary_stroke,
+                        );
+                    }
+                    BoundaryShape::Ellipse(width, height) => {
+                        let semi_axes = Vec2::new(width, height) / 2. * self.view.zoom;
+                        let points = (0..ELLIPSE_SEGMENTS)
+                            .map(|i| {
+                                let angle = TAU * i as f32 / ELLIPSE_SEGMENTS as f32;
+                                center + Vec2::angled(angle) * semi_axes
+                            })
+                            .collect();
+                        paint.add(Shape::closed_line(points, boundary_stroke));
+                    }
+                }
+
+                if let Some(comparison) = &self.comparison {
+                    let comparison_rect =
+                        Rect::from_min_max(pos2(canvas_rect.max.x, resp.rect.min.y), resp.rect.max);
+                    let comparison_paint = ui.painter_at(comparison_rect);
+                    let comparison_center = center + Vec2::new(canvas_rect.width(), 0.);
+                    for c in 0..comparison.shared.class_count {
+                        let class_color = self.classes[c].color;
+                        for p in 0..comparison.shared.particle_counts[c] {
+                            let particle_pos = comparison.particle_positions[(c, p)];
+                            let pos = comparison_center + particle_pos * self.view.zoom;
+                            if comparison_paint.clip_rect().contains(pos) {
+                                let color = match &diff_references {
+                                    Some((references, _))
+                                        if is_unmatched(references[c], particle_pos) =>
+                                    {
+                                        DIFF_HIGHLIGHT_COLOR
+                                    }
+                                    _ => class_color,
+                                };
+                                comparison_paint.circle_filled(pos, PARTICLE_DIAMETER, color);
+                            }
+                        }
+                    }
+
+                    comparison_paint.line_segment(
+                        [comparison_rect.left_top(), comparison_rect.left_bottom()],
+                        Stroke::new(1., Color32::from_gray(80)),
+                    );
+                    comparison_paint.text(
+                        comparison_rect.left_top() + Vec2::splat(OVERLAY_MARGIN),
+                        Align2::LEFT_TOP,
+                        &comparison.seed,
+                        FontId::monospace(12.),
+                        Color32::from_gray(200),
+                    );
+                }
+
+                if self.show_force_field && self.force_field_class < self.shared.class_count {
+                    let rect = canvas_rect;
+                    let shared = &self.shared;
+                    let particle_positions = &self.particle_positions;
+                    let close_force = self.close_force;
+                    let zoom = self.view.zoom;
+                    let class = self.force_field_class;
+                    let step = rect.size() / FORCE_FIELD_RESOLUTION as f32;
+                    let samples: Vec<(Pos2, Vec2)> = (0..FORCE_FIELD_RESOLUTION
+                        * FORCE_FIELD_RESOLUTION)
+                        .into_par_iter()
+                        .map(|i| {
+                            let x = (i % FORCE_FIELD_RESOLUTION) as f32 + 0.5;
+                            let y = (i / FORCE_FIELD_RESOLUTION) as f32 + 0.5;
+                            let screen_pos = rect.min + Vec2::new(x * step.x, y * step.y);
+                            let pos = (screen_pos - center) / zoom;
+                            (
+                                screen_pos,
+                                force_at(shared, particle_positions, close_force, class, pos),
+                            )
+                        })
+                        .collect();
+
+                    let max_force = samples.iter().map(|(_, f)| f.length()).fold(0., f32::max);
+                    if max_force > 0. {
+                        let max_length = 0.9 * step.min_elem();
+                        for (screen_pos, f) in samples {
+                            let intensity = f.length() / max_force;
+                            paint.arrow(
+                                screen_pos,
+                                f / max_force * max_length,
+                                Stroke::new(1., Color32::from_gray((60. + 195. * intensity) as u8)),
                             );
                         }
                     }
                 }
 
+                let overlay_color = Color32::from_gray(200);
+                let overlay_font = FontId::monospace(12.);
+
+                let ruler_start =
+                    resp.rect.left_bottom() + Vec2::new(OVERLAY_MARGIN, -OVERLAY_MARGIN);
+                let ruler_end = ruler_start + Vec2::new(RULER_LENGTH, 0.);
+                let ruler_stroke = Stroke::new(1., overlay_color);
+                paint.line_segment([ruler_start, ruler_end], ruler_stroke);
+                for end in [ruler_start, ruler_end] {
+                    paint.line_segment([end - Vec2::new(0., 4.), end], ruler_stroke);
+                }
+                paint.text(
+                    ruler_start - Vec2::new(0., 6.),
+                    Align2::LEFT_BOTTOM,
+                    format!("{:.1}", RULER_LENGTH / self.view.zoom),
+                    overlay_font.to_owned(),
+                    overlay_color,
+                );
+
+                if let Some(hover_pos) = resp.hover_pos() {
+                    let pos = (hover_pos - center) / self.view.zoom;
+                    paint.text(
+                        resp.rect.left_top() + Vec2::splat(OVERLAY_MARGIN),
+                        Align2::LEFT_TOP,
+                        format!("x: {:.1}, y: {:.1}", pos.x, pos.y),
+                        overlay_font,
+                        overlay_color,
+                    );
+                }
+
+                if self.show_minimap {
+                    Area::new("minimap")
+                        .anchor(Align2::RIGHT_BOTTOM, [-OVERLAY_MARGIN, -OVERLAY_MARGIN])
+                        .show(ctx, |ui| self.minimap(ui, resp.rect, center));
+                }
+
                 // if self.shared.simulation_state != SimulationState::Stopped {
                 //     paint.circle_stroke(
                 //         center + self.particle_positions[self.selected_particle] * self.view.zoom,
@@ -620,6 +4206,311 @@ impl App for Smarticles {
                 // }
             });
 
-        ctx.request_repaint();
+        if let Some((message, time)) = &self.toast {
+            if time.elapsed() < TOAST_DURATION {
+                Area::new("toast")
+                    .anchor(Align2::CENTER_TOP, [0., OVERLAY_MARGIN])
+                    .show(ctx, |ui| {
+                        egui::Frame::popup(ui.style()).show(ui, |ui| ui.label(message));
+                    });
+            } else {
+                self.toast = None;
+            }
+        }
+
+        match self.target_fps {
+            Some(target_fps) => ctx.request_repaint_after(Duration::from_secs_f32(1. / target_fps)),
+            None => ctx.request_repaint(),
+        }
+    }
+
+    fn on_close_event(&mut self) -> bool {
+        self.save_window_geometry();
+        true
+    }
+}
+
+/// Net velocity variation a particle of class `c1` would get if
+/// it were at `pos` (same computation as in the simulation).
+fn force_at(
+    shared: &SharedState,
+    particle_positions: &Array2D<Vec2>,
+    close_force: f32,
+    c1: usize,
+    pos: Vec2,
+) -> Vec2 {
+    let close_force = close_force * FORCE_FACTOR;
+    let mut f = Vec2::ZERO;
+    for c2 in 0..shared.class_count {
+        let param = &shared.param_matrix[(c1, c2)];
+        let force = -param.force * FORCE_FACTOR;
+        let long_range_force = -param.long_range_force * FORCE_FACTOR;
+        for p2 in 0..shared.particle_counts[c2] {
+            f += get_partial_velocity(
+                particle_positions[(c2, p2)] - pos,
+                param.radius,
+                force,
+                long_range_force,
+                close_force,
+            );
+        }
+    }
+    f / shared.class_masses[c1]
+}
+
+/// Whether this slider response starts a new change, so that a
+/// whole drag is a single undo step.
+fn starts_change(slider: &Response) -> bool {
+    slider.drag_started() || (slider.changed() && !slider.dragged())
+}
+
+/// Color halfway between `a` and `b`.
+fn blend_colors(a: Color32, b: Color32) -> Color32 {
+    let mix = |a: u8, b: u8| ((a as u16 + b as u16) / 2) as u8;
+    Color32::from_rgb(mix(a.r(), b.r()), mix(a.g(), b.g()), mix(a.b(), b.b()))
+}
+
+fn distance_to_segment(pos: Pos2, start: Pos2, end: Pos2) -> f32 {
+    let segment = end - start;
+    let t = ((pos - start).dot(segment) / segment.length_sq()).clamp(0., 1.);
+    pos.distance(start + t * segment)
+}
+
+/// Returns the bytes of a custom seed (`@...`), or `None` if the
+/// seed is not a valid custom seed.
+fn decode_seed(seed: &str) -> Option<Vec<u8>> {
+    base64::decode(seed.trim().strip_prefix('@')?).ok()
+}
+
+/// Reads the format byte of a custom seed (as bytes), returns
+/// whether its params are stored as `f32`. Seeds made before
+/// [`SEED_FORMAT_F32`] start with the class count and store the
+/// params as `i8`.
+fn read_seed_format(bytes: &mut &[u8]) -> bool {
+    if bytes.first() == Some(&SEED_FORMAT_F32) {
+        *bytes = &bytes[1..];
+        true
+    } else {
+        false
+    }
+}
+
+/// Reads a force or a radius from a custom seed (as bytes).
+fn read_seed_param(bytes: &mut &[u8], f32_params: bool) -> f32 {
+    if f32_params {
+        bytes.read_f32::<LE>().unwrap_or(0.)
+    } else {
+        bytes.read_i8().unwrap_or(0) as f32
+    }
+}
+
+/// Combines two custom seeds (as bytes) value by value using
+/// `f`, the result always stores its params as `f32`.
+fn combine_seeds<F>(mut a: &[u8], mut b: &[u8], mut f: F) -> Vec<u8>
+where
+    F: FnMut(f32, f32) -> f32,
+{
+    let a_f32_params = read_seed_format(&mut a);
+    let b_f32_params = read_seed_format(&mut b);
+
+    let mut bytes: Vec<u8> = Vec::new();
+    bytes.write_u8(SEED_FORMAT_F32).unwrap();
+    let class_count = f(
+        a.read_u8().unwrap_or(MAX_CLASSES as u8) as f32,
+        b.read_u8().unwrap_or(MAX_CLASSES as u8) as f32,
+    );
+    bytes.write_u8(class_count.round() as u8).unwrap();
+    for _ in 0..MAX_CLASSES {
+        let count = f(
+            a.read_u16::<LE>().unwrap_or(0) as f32,
+            b.read_u16::<LE>().unwrap_or(0) as f32,
+        );
+        bytes.write_u16::<LE>(count.round() as u16).unwrap();
+    }
+    // force and radius of each param
+    for _ in 0..2 * MAX_CLASSES * MAX_CLASSES {
+        let value = f(
+            read_seed_param(&mut a, a_f32_params),
+            read_seed_param(&mut b, b_f32_params),
+        );
+        bytes.write_f32::<LE>(value).unwrap();
+    }
+    // long-range force of each param, missing in older seeds
+    for _ in 0..MAX_CLASSES * MAX_CLASSES {
+        let value = f(
+            a.read_f32::<LE>().unwrap_or(0.),
+            b.read_f32::<LE>().unwrap_or(0.),
+        );
+        bytes.write_f32::<LE>(value).unwrap();
+    }
+
+    bytes
+}
+
+/// Linearly interpolates each value of two custom seeds (as
+/// bytes), `t = 0` gives `a` and `t = 1` gives `b`.
+fn blend_seeds(a: &[u8], b: &[u8], t: f32) -> Vec<u8> {
+    combine_seeds(a, b, |a, b| a * (1. - t) + b * t)
+}
+
+/// Picks each value of the resulting seed from `a` with
+/// probability `p` and from `b` otherwise.
+fn crossover_seeds(a: &[u8], b: &[u8], p: f32) -> Vec<u8> {
+    let mut rand = SmallRng::from_entropy();
+    combine_seeds(a, b, |a, b| {
+        if rand.sample::<f32, _>(Open01) < p {
+            a
+        } else {
+            b
+        }
+    })
+}
+
+/// Centroid of each enabled class and the mean distance of its
+/// particles to it.
+fn class_references(
+    shared: &SharedState,
+    particle_positions: &Array2D<Vec2>,
+) -> [Option<(Vec2, f32)>; MAX_CLASSES] {
+    let mut references = [None; MAX_CLASSES];
+    for (c, reference) in references.iter_mut().enumerate().take(shared.class_count) {
+        let count = shared.particle_counts[c];
+        if count == 0 {
+            continue;
+        }
+        let positions = (0..count).map(|p| particle_positions[(c, p)]);
+        let centroid = positions.clone().fold(Vec2::ZERO, |sum, pos| sum + pos) / count as f32;
+        let spread = positions.map(|pos| (pos - centroid).length()).sum::<f32>() / count as f32;
+        *reference = Some((centroid, spread));
+    }
+    references
+}
+
+/// Whether a particle at `pos` is away from where its class is in
+/// the other simulation (see [`class_references`]).
+fn is_unmatched(reference: Option<(Vec2, f32)>, pos: Vec2) -> bool {
+    match reference {
+        Some((centroid, spread)) => (pos - centroid).length() > DIFF_SPREAD_FACTOR * spread,
+        None => true,
+    }
+}
+
+/// Entropy of the density of the particles over a grid covering
+/// them, normalized between 0 (all in one cell) and 1 (spread
+/// evenly). Low scores mean structured patterns. `None` when there
+/// are less than two particles.
+fn pattern_score(shared: &SharedState, particle_positions: &Array2D<Vec2>) -> Option<f32> {
+    let positions = (0..shared.class_count)
+        .flat_map(|c| (0..shared.particle_counts[c]).map(move |p| particle_positions[(c, p)]));
+    let particle_count = positions.clone().count();
+    if particle_count < 2 {
+        return None;
+    }
+
+    let (min, max) = positions.clone().fold(
+        (Vec2::splat(f32::INFINITY), Vec2::splat(f32::NEG_INFINITY)),
+        |(min, max), pos| (min.min(pos), max.max(pos)),
+    );
+    let cell_size = ((max - min) / PATTERN_GRID_SIZE as f32).max(Vec2::splat(f32::EPSILON));
+    let mut cells = [[0usize; PATTERN_GRID_SIZE]; PATTERN_GRID_SIZE];
+    for pos in positions {
+        let cell = (pos - min) / cell_size;
+        let x = (cell.x as usize).min(PATTERN_GRID_SIZE - 1);
+        let y = (cell.y as usize).min(PATTERN_GRID_SIZE - 1);
+        cells[y][x] += 1;
+    }
+
+    let entropy = cells
+        .iter()
+        .flatten()
+        .filter(|count| **count > 0)
+        .map(|count| {
+            let p = *count as f32 / particle_count as f32;
+            -p * p.ln()
+        })
+        .sum::<f32>();
+    // Max entropy, when every particle can have its own cell.
+    let max_entropy = (particle_count.min(PATTERN_GRID_SIZE * PATTERN_GRID_SIZE) as f32).ln();
+    Some(entropy / max_entropy)
+}
+
+/// Draws the times as a stacked bar where each one takes its share
+/// of the width, followed by their values.
+fn time_breakdown(ui: &mut Ui, id: &str, steps: &[(&str, Duration, Color32)]) {
+    let total = steps
+        .iter()
+        .map(|(_, time, _)| time.as_secs_f32())
+        .sum::<f32>();
+
+    let (rect, _) = ui.allocate_exact_size(
+        Vec2::new(ui.available_width(), PERF_BAR_HEIGHT),
+        Sense::hover(),
+    );
+    let paint = ui.painter_at(rect);
+    paint.rect_filled(rect, 0., Color32::from_gray(30));
+    if total > 0. {
+        let mut x = rect.left();
+        for (_, time, color) in steps {
+            let width = rect.width() * time.as_secs_f32() / total;
+            paint.rect_filled(
+                Rect::from_min_size(pos2(x, rect.top()), Vec2::new(width, rect.height())),
+                0.,
+                *color,
+            );
+            x += width;
+        }
+    }
+
+    Grid::new(id).show(ui, |ui| {
+        for (label, time, color) in steps {
+            ui.colored_label(*color, *label);
+            ui.code(format!("{:.2}ms", time.as_secs_f32() * 1000.));
+            ui.end_row();
+        }
+    });
+}
+
+/// Color of a force (or of a difference of forces) everywhere in
+/// the ui, green for attraction (negative forces) and red for
+/// repulsion. Forces of `max_force` or more get the full
+/// intensity, `force.abs()` gives a saturated color.
+fn force_color(force: f32, max_force: f32) -> Color32 {
+    let intensity = (20. + 235. * (force.abs() / max_force).min(1.)) as u8;
+    if force < 0. {
+        Color32::from_rgb(20, intensity, 20)
+    } else {
+        Color32::from_rgb(intensity, 20, 20)
+    }
+}
+
+/// Draws the forces of a seed as a tiny heatmap.
+fn force_preview(ui: &mut Ui, preview: &SeedPreview) {
+    let (rect, _) = ui.allocate_exact_size(Vec2::splat(SEED_PREVIEW_SIZE), Sense::hover());
+    let cell_size = SEED_PREVIEW_SIZE / preview.class_count.max(1) as f32;
+    for i in 0..preview.class_count {
+        for j in 0..preview.class_count {
+            ui.painter().rect_filled(
+                Rect::from_min_size(
+                    rect.min + Vec2::new(j as f32, i as f32) * cell_size,
+                    Vec2::splat(cell_size),
+                ),
+                0.,
+                force_color(preview.forces[(i, j)], MAX_FORCE),
+            );
+        }
+    }
+}
+
+/// Formats a number with spaces between groups of three digits
+/// (e.g. `1 234 567`).
+fn format_thousands(n: u64) -> String {
+    let digits = n.to_string();
+    let mut formatted = String::new();
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i) % 3 == 0 {
+            formatted.push(' ');
+        }
+        formatted.push(digit);
     }
+    formatted
 }